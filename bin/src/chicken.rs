@@ -577,10 +577,7 @@ fn pattern_to_chicken<T: HashLoader>(
                     vbls,
                     list(vec![
                         atom("list-ref"),
-                        list(vec![
-                            atom("caddr"),
-                            term.clone(),
-                        ]),
+                        list(vec![atom("caddr"), term.clone()]),
                         atom(&format!("{}", i + 1)),
                     ]),
                     body,
@@ -605,15 +602,15 @@ fn pattern_to_chicken<T: HashLoader>(
                     ]),
                     list(vec![
                         atom("equal?"),
-                        list(vec![atom("length"), list(vec![atom("caddr"), term.clone() ]) ]),
+                        list(vec![
+                            atom("length"),
+                            list(vec![atom("caddr"), term.clone()]),
+                        ]),
                         atom(&format!("{}", args.len() + 1)),
                     ]),
                     list(vec![
                         atom("equal?"),
-                        list(vec![
-                            atom("car"),
-                            list(vec![atom("caddr"), term.clone()]),
-                        ]),
+                        list(vec![atom("car"), list(vec![atom("caddr"), term.clone()])]),
                         atom(&format!("'{}_{}", id.to_string(), num)),
                     ]),
                 ]),
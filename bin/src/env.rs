@@ -10,6 +10,24 @@ pub type Result<T> = std::result::Result<T, Error>;
 pub enum Error {
     TermNotFound(String),
     NotImplemented(String),
+    // The term uses a builtin that isn't in the `BuiltinRegistry`
+    UnknownBuiltin(String),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::TermNotFound(hash) => write!(fmt, "Term not found: {}", hash),
+            Error::NotImplemented(what) => write!(fmt, "Not implemented: {}", what),
+            Error::UnknownBuiltin(name) => write!(fmt, "Unsupported builtin: {}", name),
+        }
+    }
+}
+
+impl From<Error> for std::io::Error {
+    fn from(err: Error) -> Self {
+        std::io::Error::new(std::io::ErrorKind::Other, err.to_string())
+    }
 }
 
 fn concat(mut buf: std::path::PathBuf, thing: String) -> std::path::PathBuf {
@@ -0,0 +1,255 @@
+// The native event loop, for requests that can't be handled synchronously.
+//
+// When a request bails out of a run (see `FFI::handle_request`), its handler gets
+// the arguments and a `Continuation`. It can resume that continuation any number of
+// times (including none), right away, after a delay, or from another thread once
+// some IO completes. The loop keeps going until there's nothing left that could
+// resume anything.
+//
+// Requests with a future handler (see `Handlers::add_future`) don't come through
+// here; they're awaited on the tokio runtime, which resumed continuations run on too.
+use crate::ffi::RustFFI;
+use shared::error::{Failure, RuntimeError};
+use shared::frame::Frame;
+use shared::policy::PolicyFFI;
+use shared::state::{FullRequest, State};
+use shared::types::*;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+
+// Where a request was made. Resuming it continues the program from there, with
+// the value as the result of the request.
+#[derive(Debug, Clone)]
+pub struct Continuation {
+    pub kind: Reference,
+    pub number: usize,
+    frames: Vec<Frame>,
+    final_index: usize,
+}
+
+impl Continuation {
+    fn state<'a>(&self, env: &'a RuntimeEnv, value: Arc<Value>) -> Result<State<'a>, Failure> {
+        State::full_resume(
+            env,
+            self.kind.clone(),
+            self.number,
+            self.frames.clone(),
+            self.final_index,
+            value,
+        )
+    }
+}
+
+// Gets the request's arguments & continuation, and the queue to schedule resumptions on.
+pub type AsyncHandler =
+    Box<dyn FnMut(&[Arc<Value>], Continuation, &mut Queue) -> Result<(), String>>;
+
+type Work = (Continuation, Arc<Value>);
+
+// The continuations waiting to be resumed.
+pub struct Queue {
+    ready: VecDeque<Work>,
+    timers: Vec<(Instant, Work)>,
+    sender: mpsc::Sender<Work>,
+    receiver: mpsc::Receiver<Work>,
+    // the number of `Completer`s that haven't been used yet
+    outstanding: Arc<AtomicUsize>,
+}
+
+// Resumes a continuation from another thread. The loop waits for every
+// completer to be used or dropped before finishing.
+pub struct Completer {
+    sender: mpsc::Sender<Work>,
+    outstanding: Arc<AtomicUsize>,
+}
+
+impl Completer {
+    pub fn complete(self, k: Continuation, value: Value) {
+        // If the loop is gone, there's nobody to resume
+        let _ = self.sender.send((k, Arc::new(value)));
+    }
+}
+
+impl Drop for Completer {
+    fn drop(&mut self) {
+        self.outstanding.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Queue {
+    fn new() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Queue {
+            ready: VecDeque::new(),
+            timers: vec![],
+            sender,
+            receiver,
+            outstanding: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub fn resume(&mut self, k: Continuation, value: Value) {
+        self.ready.push_back((k, Arc::new(value)));
+    }
+
+    pub fn resume_after(&mut self, delay: Duration, k: Continuation, value: Value) {
+        self.timers
+            .push((Instant::now() + delay, (k, Arc::new(value))));
+    }
+
+    pub fn completer(&self) -> Completer {
+        self.outstanding.fetch_add(1, Ordering::SeqCst);
+        Completer {
+            sender: self.sender.clone(),
+            outstanding: self.outstanding.clone(),
+        }
+    }
+
+    fn next(&mut self) -> Option<Work> {
+        while let Ok(work) = self.receiver.try_recv() {
+            self.ready.push_back(work);
+        }
+        let now = Instant::now();
+        // Timers are kept in the order they were set, so equal delays resume in order
+        let mut i = 0;
+        while i < self.timers.len() {
+            if self.timers[i].0 <= now {
+                let (_, work) = self.timers.remove(i);
+                self.ready.push_back(work);
+            } else {
+                i += 1;
+            }
+        }
+        self.ready.pop_front()
+    }
+
+    // Blocks until there might be something to do. Returns false if there
+    // never will be.
+    fn wait(&mut self) -> bool {
+        if !self.ready.is_empty() {
+            return true;
+        }
+        let timeout = match self.timers.iter().map(|(when, _)| *when).min() {
+            Some(when) => when.saturating_duration_since(Instant::now()),
+            None => {
+                // Any sends happen before the completer is dropped, so once
+                // they're all gone, the channel has everything it'll ever get.
+                if self.outstanding.load(Ordering::SeqCst) == 0 {
+                    return match self.receiver.try_recv() {
+                        Ok(work) => {
+                            self.ready.push_back(work);
+                            true
+                        }
+                        Err(_) => false,
+                    };
+                }
+                // Poll, in case a completer is dropped without being used
+                Duration::from_millis(100)
+            }
+        };
+        if let Ok(work) = self.receiver.recv_timeout(timeout) {
+            self.ready.push_back(work);
+        }
+        true
+    }
+}
+
+// The requests that have bailed out, and the handlers for them.
+pub struct EventLoop {
+    requests: Vec<FullRequest>,
+    handlers: HashMap<(Reference, usize), AsyncHandler>,
+    pub queue: Queue,
+}
+
+impl EventLoop {
+    pub fn new() -> Self {
+        EventLoop {
+            requests: vec![],
+            handlers: HashMap::new(),
+            queue: Queue::new(),
+        }
+    }
+
+    // Replaces any existing handler for the constructor.
+    pub fn add(&mut self, kind: Reference, number: usize, handler: AsyncHandler) {
+        self.handlers.insert((kind, number), handler);
+    }
+
+    pub fn handles(&self, kind: &Reference) -> bool {
+        self.handlers.keys().any(|(reference, _)| reference == kind)
+    }
+
+    pub fn push(&mut self, request: FullRequest) {
+        self.requests.push(request);
+    }
+
+    // Hands the requests that have come in to their handlers.
+    fn dispatch(&mut self) -> Result<(), Failure> {
+        for FullRequest(kind, number, args, frames, final_index, _t) in
+            std::mem::replace(&mut self.requests, vec![])
+        {
+            let handler = match self.handlers.get_mut(&(kind.clone(), number)) {
+                Some(handler) => handler,
+                None => {
+                    return Err(Failure::new(
+                        RuntimeError::UnknownAbility(kind, number),
+                        &frames,
+                        final_index,
+                    ))
+                }
+            };
+            let k = Continuation {
+                kind: kind.clone(),
+                number,
+                frames,
+                final_index,
+            };
+            // just the top frame, for the error
+            let frames = k.frames[..1].to_vec();
+            handler(&args, k, &mut self.queue).map_err(|message| {
+                Failure::new(
+                    RuntimeError::Internal(format!(
+                        "Bad request for {:?} # {}: {}",
+                        kind, number, message
+                    )),
+                    &frames,
+                    final_index,
+                )
+            })?;
+        }
+        Ok(())
+    }
+}
+
+// Runs until there's nothing left to do. Whenever a resumed continuation
+// runs to completion, `on_result` gets its value.
+// The budget is for the whole run, and `steps` of it were used before we got here.
+pub fn run<F: FnMut(Arc<Value>)>(
+    rt: &mut tokio::runtime::Runtime,
+    ffi: &mut PolicyFFI<RustFFI>,
+    env: &RuntimeEnv,
+    budget: &shared::budget::Budget,
+    mut steps: usize,
+    trace: &mut shared::chrome_trace::Traces,
+    mut on_result: F,
+) -> Result<(), Failure> {
+    loop {
+        ffi.inner.1.dispatch()?;
+        if let Some((k, value)) = ffi.inner.1.queue.next() {
+            let mut state = k.state(env, value)?;
+            state.budget = budget.clone();
+            state.steps = steps;
+            let result = rt.block_on(shared::concurrent::run_to_end(env, &mut state, ffi, trace));
+            steps = state.steps;
+            if let Some(result) = result? {
+                on_result(result);
+            }
+            continue;
+        }
+        if !ffi.inner.1.queue.wait() {
+            return Ok(());
+        }
+    }
+}
@@ -1,61 +1,104 @@
-// #ed72l2mrh0
-use shared::state::{FullRequest, InvalidFFI, State};
+use crate::event_loop::{Continuation, EventLoop, Queue};
+use log::info;
+use shared::async_ffi::{AsyncFFI, ValueFuture};
+use shared::error::RuntimeError;
+use shared::handlers::Handlers;
+use shared::state::FullRequest;
 use shared::types::*;
+use std::collections::HashMap;
+use std::sync::Arc;
 
-fn unit() -> Value {
-    Value::Constructor(
-    Reference::DerivedId(Id::from_string(
-        "568rsi7o3ghq8mmbea2sf8msdk20ohasob5s2rvjtqg2lr0vs39l1hm98urrjemsr3vo3fa52pibqu0maluq7g8sfg3h5f5re6vitj8"
-    )), 0)
+// Requests are handled by `native.IO`, then by the registered handlers. The ones
+// that bail out are awaited if they have a future handler, and otherwise queued up
+// for the event loop's handlers.
+// Embedders can add their own with e.g. `ffi.3.add("my.Ability", 0, |n: u64| n + 1)`.
+pub struct RustFFI(
+    pub crate::printer::FlatNames,
+    pub EventLoop,
+    pub crate::io::NativeIO,
+    pub Handlers,
+);
+
+// Every type's fully-qualified names, for registering handlers by ability name.
+pub fn ability_names(names: &crate::pack::Names<Id>) -> HashMap<String, Reference> {
+    let mut abilities = HashMap::new();
+    for (id, names) in names.types.iter() {
+        for name in names {
+            abilities.insert(name.join("."), Reference::DerivedId(id.clone()));
+        }
+    }
+    abilities
 }
 
-pub struct RustFFI(pub crate::printer::FlatNames, pub Vec<FullRequest>);
 impl RustFFI {
-    pub fn has_next_request(&self) -> bool {
-        self.1.len() > 0
+    pub fn new(
+        names: crate::printer::FlatNames,
+        io: crate::io::NativeIO,
+        abilities: HashMap<String, Reference>,
+    ) -> Self {
+        let mut handlers = Handlers::new(abilities);
+        default_handlers(&mut handlers, &names);
+        let mut ffi = RustFFI(names, EventLoop::new(), io, handlers);
+        // The example ability, `Both.both : a -> a ->{Both} a`, which resumes with
+        // each of its arguments
+        let both = ffi.add_async("Both", 0, |args, k, queue| match args {
+            [one, two] => {
+                queue.resume(k.clone(), (**one).clone());
+                queue.resume(k, (**two).clone());
+                Ok(())
+            }
+            _ => Err(format!("Expected 2 arguments, got {}", args.len())),
+        });
+        if let Err(message) = both {
+            info!("Skipping a default handler: {}", message);
+        }
+        ffi
     }
 
-    pub fn process_next_request(
-        &mut self,
-        env: &RuntimeEnv,
-        trace: &mut shared::chrome_trace::Traces,
-    ) -> Result<(), InvalidFFI> {
-        let FullRequest(kind, number, args, frames, final_index, _t) = self.1.remove(0);
-        match &kind {
-            Reference::DerivedId(id) => match (&id.to_string()[0..10], number) {
-                ("onasci86q4", 0) => {
-                    State::full_resume(
-                        env,
-                        kind.clone(),
-                        number,
-                        frames.clone(),
-                        final_index,
-                        args[0].clone(),
-                    )?
-                    .run_to_end(self, trace)?
-                    .map(|v| {
-                        println!(
-                            "first run -> {}",
-                            crate::printer::value_to_pretty(&v, &self.0, 100)
-                        )
-                    });
-                    State::full_resume(env, kind, number, frames, final_index, args[1].clone())?
-                        .run_to_end(self, trace)?
-                        .map(|v| {
-                            println!(
-                                "second run -> {}",
-                                crate::printer::value_to_pretty(&v, &self.0, 100)
-                            )
-                        });
-                }
-                _ => unreachable!(),
-            },
-            _ => unreachable!(),
-        }
+    // For requests that need the continuation, e.g. to resume it later or more
+    // than once. They're handled by the event loop (see `event_loop::run`).
+    pub fn add_async<F>(&mut self, ability: &str, number: usize, handler: F) -> Result<(), String>
+    where
+        F: FnMut(&[Arc<Value>], Continuation, &mut Queue) -> Result<(), String> + 'static,
+    {
+        let kind = self
+            .3
+            .resolve(ability)
+            .ok_or_else(|| format!("Ability {} not found", ability))?;
+        self.1.add(kind, number, Box::new(handler));
         Ok(())
     }
 }
 
+// The abilities the development environment's js handlers also provide.
+// Any that aren't in the codebase are skipped.
+fn default_handlers(handlers: &mut Handlers, names: &crate::printer::FlatNames) {
+    let mut results = vec![handlers.add("Time", 0, || {
+        std::time::SystemTime::UNIX_EPOCH
+            .elapsed()
+            .unwrap()
+            .as_millis() as u64
+    })];
+    // log, warn & error
+    for number in 0..3 {
+        let names = names.clone();
+        results.push(handlers.add("Console", number, move |value: Value| {
+            println!(
+                "[LOG] {}",
+                crate::printer::value_to_pretty(&value, &names, 100)
+            )
+        }));
+    }
+    results.push(handlers.add("Wait", 0, |millis: u64| {
+        std::thread::sleep(std::time::Duration::from_millis(millis))
+    }));
+    for result in results {
+        if let Err(message) = result {
+            info!("Skipping a default handler: {}", message);
+        }
+    }
+}
+
 impl shared::ffi::FFI for RustFFI {
     fn handle_request_sync(
         &mut self,
@@ -63,63 +106,22 @@ impl shared::ffi::FFI for RustFFI {
         kind: &Reference,
         number: usize,
         args: &Vec<std::sync::Arc<Value>>,
-    ) -> Option<Value> {
-        // println!("Asking for a request {:?} : {} : {:?}", kind, number, args);
-        match kind {
-            Reference::DerivedId(id) => {
-                match &id.to_string()[0..10] {
-                    "ed72l2mrh0" => {
-                        // currentTimeStampMs
-                        if number == 0 {
-                            Some(Value::Nat(
-                                std::time::SystemTime::UNIX_EPOCH
-                                    .elapsed()
-                                    .unwrap()
-                                    .as_millis() as u64,
-                            ))
-                        } else {
-                            unreachable!();
-                        }
-                    }
-                    "s81fshin91" => {
-                        println!(
-                            "[LOG] {}",
-                            args.iter()
-                                .map(|arg| crate::printer::value_to_pretty(arg, &self.0, 100))
-                                .collect::<Vec<String>>()
-                                .join(", ")
-                        );
-                        return Some(unit());
-                        // number 0, 1, 2 determines log, error or warn
-                        // if number === "log"
-                    }
-                    "mvd13op0i1" => {
-                        match *args[0] {
-                            Value::Nat(millis) => {
-                                std::thread::sleep(std::time::Duration::from_millis(millis))
-                            }
-                            _ => unreachable!("Expected a Nat"),
-                        }
-                        Some(unit())
-                    }
-                    _ => None,
-                }
-            }
-            _ => None,
+    ) -> Result<Option<Value>, RuntimeError> {
+        if self.2.handles(kind) {
+            return self.2.handle(kind, number, args);
+        }
+        if self.3.handles_future(kind, number) {
+            return Ok(None);
+        }
+        match self.3.handle(kind, number, args) {
+            None => Ok(None),
+            Some(Ok(value)) => Ok(Some(value)),
+            Some(Err(message)) => Err(RuntimeError::InvalidRequest(kind.clone(), number, message)),
         }
     }
 
     fn handles(&self, kind: &Reference) -> bool {
-        match kind {
-            Reference::DerivedId(id) => match &id.to_string()[0..10] {
-                "onasci86q4" => true,
-                "ed72l2mrh0" => true,
-                "s81fshin91" => true,
-                "mvd13op0i1" => true,
-                _ => false,
-            },
-            _ => false,
-        }
+        self.2.handles(kind) || self.3.handles(kind) || self.1.handles(kind)
     }
 
     // This is used at the top level, once we've bailed.
@@ -127,3 +129,15 @@ impl shared::ffi::FFI for RustFFI {
         self.1.push(request);
     }
 }
+
+impl AsyncFFI for RustFFI {
+    fn handle_request_async(
+        &mut self,
+        _t: &ABT<Type>,
+        kind: &Reference,
+        number: usize,
+        args: &Vec<Arc<Value>>,
+    ) -> Option<ValueFuture> {
+        self.3.handle_future(kind, number, args)
+    }
+}
@@ -0,0 +1,320 @@
+// A native implementation of the `native.IO` ability (defined in `bin/native.u`),
+// so that command-line tools written in unison can be run with `unison.rs run`.
+//
+// Handles are Nats: 0, 1 & 2 are stdin, stdout & stderr, and files get the ones after that.
+// Operations that can fail return an `Either Text a`, with the error message on the `Left`.
+use shared::builtins::Refs;
+use shared::error::RuntimeError;
+use shared::policy::{Policy, PolicyFFI};
+use shared::types::*;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::{BufRead, Read, Seek, Write};
+use std::sync::Arc;
+
+const ABILITY: &str = "native.IO";
+
+// The operations whose first argument is a file path, which a policy can restrict
+const PATH_OPERATIONS: &[&str] = &[
+    "openFile",
+    "fileExists",
+    "isDirectory",
+    "directoryContents",
+    "createDirectory",
+    "removeFile",
+];
+
+// Wraps the ffi in the policy, telling it which arguments are paths.
+pub fn sandbox<T: shared::ffi::FFI>(ffi: T, policy: Policy, env: &RuntimeEnv) -> PolicyFFI<T> {
+    PATH_OPERATIONS
+        .iter()
+        .fold(PolicyFFI::new(ffi, policy, env), |ffi, op| {
+            ffi.path_arg(&format!("{}.{}", ABILITY, op), 0)
+        })
+}
+
+pub struct NativeIO {
+    // The ability's reference, and the names & return types of its operations
+    ability: Option<(Reference, Vec<(String, ABT<Type>)>)>,
+    refs: Refs,
+    args: Vec<String>,
+    files: HashMap<u64, std::io::BufReader<std::fs::File>>,
+    next_handle: u64,
+}
+
+impl NativeIO {
+    // `args` are the program arguments, available through `getArgs`
+    pub fn new(env: &RuntimeEnv, args: Vec<String>) -> std::io::Result<Self> {
+        Ok(NativeIO {
+            ability: find_ability(env)?,
+            refs: env.builtins.refs.clone(),
+            args,
+            files: HashMap::new(),
+            next_handle: 3,
+        })
+    }
+
+    pub fn handles(&self, kind: &Reference) -> bool {
+        match &self.ability {
+            Some((reference, _)) => reference == kind,
+            None => false,
+        }
+    }
+
+    // Returns `None` if this isn't a `native.IO` request. `exit` fails the run with
+    // `RuntimeError::Exit`, so the host decides what happens.
+    pub fn handle(
+        &mut self,
+        kind: &Reference,
+        number: usize,
+        args: &[Arc<Value>],
+    ) -> Result<Option<Value>, RuntimeError> {
+        let (name, result) = match &self.ability {
+            Some((reference, ops)) if reference == kind => match ops.get(number) {
+                Some(op) => op.clone(),
+                None => return Ok(None),
+            },
+            _ => return Ok(None),
+        };
+        let either = result.as_tm().and_then(|tm| tm.as_reference());
+        let args: Vec<&Value> = args.iter().map(|arg| &**arg).collect();
+        let value = match (name.as_str(), args.as_slice()) {
+            ("getArgs", []) => Value::Sequence(
+                self.args
+                    .iter()
+                    .map(|arg| Arc::new(Value::Text(arg.clone())))
+                    .collect(),
+            ),
+            ("getEnv", [Value::Text(name)]) => match std::env::var(name) {
+                Ok(value) => self.refs.some(Arc::new(Value::Text(value))),
+                Err(_) => self.refs.none(),
+            },
+            ("systemTime", []) => Value::Nat(since_epoch().as_secs()),
+            ("systemTimeMillis", []) => Value::Nat(since_epoch().as_millis() as u64),
+            ("exit", [Value::Nat(code)]) => {
+                return Err(match i32::try_from(*code) {
+                    Ok(code) => RuntimeError::Exit(code),
+                    Err(_) => RuntimeError::UnexpectedValue(
+                        "an exit code".to_owned(),
+                        Box::new(Value::Nat(*code)),
+                    ),
+                })
+            }
+            ("fileExists", [Value::Text(path)]) => {
+                Value::Boolean(std::path::Path::new(path).exists())
+            }
+            ("isDirectory", [Value::Text(path)]) => {
+                Value::Boolean(std::path::Path::new(path).is_dir())
+            }
+            (name, args) => {
+                let either = match either {
+                    Some(either) => either,
+                    None => return Ok(None),
+                };
+                let result = self.run(name, args);
+                self.either(either, result)
+            }
+        };
+        Ok(Some(value))
+    }
+
+    fn either(&self, either: Reference, result: std::io::Result<Value>) -> Value {
+        let (number, value) = match result {
+            Ok(value) => (1, value),
+            Err(err) => (0, Value::Text(err.to_string())),
+        };
+        Value::PartialConstructor(either, number, im::Vector::from(vec![Arc::new(value)]))
+    }
+
+    fn unit(&self) -> Value {
+        Value::Constructor(self.refs.unit.clone(), 0)
+    }
+
+    // The operations that can fail
+    fn run(&mut self, name: &str, args: &[&Value]) -> std::io::Result<Value> {
+        match (name, args) {
+            ("openFile", [Value::Text(path), Value::Text(mode)]) => {
+                let mut options = std::fs::OpenOptions::new();
+                match mode.as_str() {
+                    "r" => options.read(true),
+                    "w" => options.write(true).create(true).truncate(true),
+                    "a" => options.append(true).create(true),
+                    "rw" => options.read(true).write(true).create(true),
+                    _ => return Err(invalid(format!("Unknown file mode {:?}", mode))),
+                };
+                let file = options.open(path)?;
+                let handle = self.next_handle;
+                self.next_handle += 1;
+                self.files.insert(handle, std::io::BufReader::new(file));
+                Ok(Value::Nat(handle))
+            }
+            ("close", [Value::Nat(handle)]) => match self.files.remove(handle) {
+                Some(mut file) => {
+                    file.get_mut().flush()?;
+                    Ok(self.unit())
+                }
+                None => Err(invalid(format!("Handle {} isn't open", handle))),
+            },
+            ("getLine", [Value::Nat(handle)]) => {
+                let mut line = String::new();
+                let read = match handle {
+                    0 => std::io::stdin().lock().read_line(&mut line)?,
+                    _ => self.file(*handle)?.read_line(&mut line)?,
+                };
+                Ok(if read == 0 {
+                    self.refs.none()
+                } else {
+                    if line.ends_with('\n') {
+                        line.pop();
+                        if line.ends_with('\r') {
+                            line.pop();
+                        }
+                    }
+                    self.refs.some(Arc::new(Value::Text(line)))
+                })
+            }
+            ("getText", [Value::Nat(handle)]) => {
+                let mut text = String::new();
+                match handle {
+                    0 => std::io::stdin().lock().read_to_string(&mut text)?,
+                    _ => self.file(*handle)?.read_to_string(&mut text)?,
+                };
+                Ok(Value::Text(text))
+            }
+            ("getBytes", [Value::Nat(handle), Value::Nat(max)]) => {
+                let mut bytes = vec![];
+                match handle {
+                    0 => std::io::stdin().lock().take(*max).read_to_end(&mut bytes)?,
+                    _ => self.file(*handle)?.take(*max).read_to_end(&mut bytes)?,
+                };
+                Ok(Value::Bytes(bytes.into()))
+            }
+            ("putText", [Value::Nat(handle), Value::Text(text)]) => {
+                self.write(*handle, text.as_bytes())?;
+                Ok(self.unit())
+            }
+            ("putBytes", [Value::Nat(handle), Value::Bytes(bytes)]) => {
+                self.write(*handle, &bytes.iter().cloned().collect::<Vec<u8>>())?;
+                Ok(self.unit())
+            }
+            ("flush", [Value::Nat(handle)]) => {
+                match handle {
+                    1 => std::io::stdout().flush()?,
+                    2 => std::io::stderr().flush()?,
+                    _ => self.file(*handle)?.get_mut().flush()?,
+                };
+                Ok(self.unit())
+            }
+            ("directoryContents", [Value::Text(path)]) => {
+                let mut names = vec![];
+                for entry in std::fs::read_dir(path)? {
+                    names.push(entry?.file_name().to_string_lossy().into_owned());
+                }
+                names.sort();
+                Ok(Value::Sequence(
+                    names
+                        .into_iter()
+                        .map(|name| Arc::new(Value::Text(name)))
+                        .collect(),
+                ))
+            }
+            ("createDirectory", [Value::Text(path)]) => {
+                std::fs::create_dir_all(path)?;
+                Ok(self.unit())
+            }
+            ("removeFile", [Value::Text(path)]) => {
+                std::fs::remove_file(path)?;
+                Ok(self.unit())
+            }
+            _ => Err(invalid(format!(
+                "Unknown {} operation {} with arguments {:?}",
+                ABILITY, name, args
+            ))),
+        }
+    }
+
+    fn file(&mut self, handle: u64) -> std::io::Result<&mut std::io::BufReader<std::fs::File>> {
+        self.files
+            .get_mut(&handle)
+            .ok_or_else(|| invalid(format!("Handle {} isn't open", handle)))
+    }
+
+    fn write(&mut self, handle: u64, data: &[u8]) -> std::io::Result<()> {
+        match handle {
+            0 => Err(invalid("Can't write to stdin".to_owned())),
+            1 => std::io::stdout().write_all(data),
+            2 => std::io::stderr().write_all(data),
+            _ => {
+                // A file opened with "rw" might have been read from, and the reader is
+                // ahead of where the program is, so we throw away what it buffered.
+                let file = self.file(handle)?;
+                let position = file.stream_position()?;
+                file.seek(std::io::SeekFrom::Start(position))?;
+                file.get_mut().write_all(data)
+            }
+        }
+    }
+}
+
+fn invalid(message: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, message)
+}
+
+fn since_epoch() -> std::time::Duration {
+    std::time::SystemTime::UNIX_EPOCH
+        .elapsed()
+        .unwrap_or_default()
+}
+
+// The ability's constructors are named after it, e.g. `native.IO.getLine`. We also
+// match on suffixes, in case the definitions were added under another namespace,
+// but if there's more than one of those (and no `native.IO` itself), we can't
+// tell which one the program means.
+fn find_ability(
+    env: &RuntimeEnv,
+) -> std::io::Result<Option<(Reference, Vec<(String, ABT<Type>)>)>> {
+    let mut exact = vec![];
+    let mut namespaced = vec![];
+    for (id, decl) in env.types.iter() {
+        let data = match decl {
+            TypeDecl::Effect(data) => data,
+            _ => continue,
+        };
+        let mut ops = vec![];
+        let mut is_exact = true;
+        for (sym, typ) in data.constructors.iter() {
+            let (prefix, name) = match sym.text.rfind('.') {
+                Some(idx) => (&sym.text[..idx], &sym.text[idx + 1..]),
+                None => break,
+            };
+            if prefix != ABILITY && !prefix.ends_with(&format!(".{}", ABILITY)) {
+                break;
+            }
+            is_exact = is_exact && prefix == ABILITY;
+            let (_, _, result) = shared::ir_runtime::extract_args(typ);
+            ops.push((name.to_owned(), result));
+        }
+        if ops.len() > 0 && ops.len() == data.constructors.len() {
+            let found = (Reference::DerivedId(id.clone()), ops);
+            if is_exact {
+                exact.push(found);
+            } else {
+                namespaced.push(found);
+            }
+        }
+    }
+    let candidates = if exact.is_empty() { namespaced } else { exact };
+    if candidates.len() > 1 {
+        let mut refs: Vec<String> = candidates
+            .iter()
+            .map(|(reference, _)| format!("{:?}", reference))
+            .collect();
+        refs.sort();
+        return Err(invalid(format!(
+            "There's more than one {} ability in the codebase: {}",
+            ABILITY,
+            refs.join(", ")
+        )));
+    }
+    Ok(candidates.into_iter().next())
+}
@@ -1,5 +1,6 @@
 use super::env;
 use crate::env::Result;
+use shared::builtins::BuiltinRegistry;
 use shared::types::*;
 use std::collections::HashMap;
 
@@ -87,15 +88,21 @@ pub struct TranslationEnv {
     pub terms: HashMap<Id, (Vec<IR>, ABT<Type>)>,
     types: HashMap<Id, TypeDecl>,
     pub anon_fns: Vec<(Id, Vec<IR>)>, // I think?
+    // Any builtins that aren't in here are reported when loading.
+    pub builtins: BuiltinRegistry,
 }
 
 impl Into<RuntimeEnv> for TranslationEnv {
     fn into(self) -> RuntimeEnv {
-        RuntimeEnv {
+        let mut env = RuntimeEnv {
             terms: self.terms,
             types: self.types,
             anon_fns: self.anon_fns,
-        }
+            builtins: self.builtins,
+            memo: Default::default(),
+        };
+        env.find_either();
+        env
     }
 }
 
@@ -106,6 +113,7 @@ impl TranslationEnv {
             terms: HashMap::new(),
             types: HashMap::new(),
             anon_fns: vec![],
+            builtins: Default::default(),
         }
     }
 
@@ -134,7 +142,11 @@ impl TranslationEnv {
             ),
         );
         let (term, typ) = self.env.load(&hash.to_string())?;
-        term.to_ir(&mut cmds, self)?;
+        if let Err(err) = term.to_ir(&mut cmds, self) {
+            // Don't leave the placeholder around
+            self.terms.remove(hash);
+            return Err(err);
+        }
 
         resolve_marks(&mut cmds.cmds);
 
@@ -252,7 +264,16 @@ impl ToIR for Term {
                 let done_mk = cmds.mark();
                 cmds.push(IR::Mark(done_mk));
             }
-            Term::Ref(Reference::Builtin(_)) => cmds.push(IR::Value(self.clone().into())),
+            Term::Ref(Reference::Builtin(name)) => match env.builtins.get(name) {
+                None => return Err(env::Error::UnknownBuiltin(name.clone())),
+                // Constants can be inlined
+                Some(builtin) if builtin.arity == 0 => cmds.push(IR::Value(
+                    env.builtins
+                        .call(name, vec![])
+                        .map_err(|err| env::Error::NotImplemented(err.to_string()))?,
+                )),
+                Some(_) => cmds.push(IR::Value(self.clone().into())),
+            },
             Term::Ref(Reference::DerivedId(id)) => {
                 env.load(&id)?;
                 cmds.push(IR::Value(self.clone().into()))
@@ -10,7 +10,9 @@ mod base32hex;
 mod branch;
 mod chicken;
 mod env;
+mod event_loop;
 mod ffi;
+mod io;
 mod ir;
 mod pack;
 mod parser;
@@ -28,10 +30,41 @@ Usage:
 - unison.rs serve                     : run the development environment. view it at http://127.0.0.1:3030
 - unison.rs serve path/to/codebase    : specify the codebase root (the default is ~/.unison/v1)
 - unison.rs serve --override some/dir : provide an override directory for serving custom javascript files
+- unison.rs serve --policy policy.json : the capability policy for the client to run terms with
+- unison.rs run .some.term arg1 arg2  : run a term from the default codebase
+- unison.rs run --max-steps 1000000 --timeout-ms 5000 .some.term
+                                      : bail out once the instruction count or time limit is reached
+- unison.rs run --allow native.IO --deny native.IO.removeFile --allow-dir ./data --max-calls 100 .some.term
+                                      : restrict what the term can do with abilities (also --policy policy.json)
+- unison.rs run --json .some.term     : print the result as JSON (records are objects, options are null or the value)
+- unison.rs builtins                  : list the unison builtins that aren't supported yet
 "#
     );
 }
 
+fn builtins() {
+    let registry = shared::builtins::BuiltinRegistry::default();
+    let unsupported = registry.unsupported();
+    println!(
+        "{} builtins supported, {} unsupported:",
+        registry.names().len(),
+        unsupported.len()
+    );
+    for name in unsupported {
+        println!("- {}", name);
+    }
+}
+
+// Pulls `flag value` out of the args, wherever it is.
+fn take_flag(args: &mut Vec<String>, flag: &str) -> Option<String> {
+    let idx = args.iter().position(|arg| arg == flag)?;
+    if idx + 1 >= args.len() {
+        return None;
+    }
+    args.remove(idx);
+    Some(args.remove(idx))
+}
+
 fn main() -> std::io::Result<()> {
     env_logger::init();
     let mut args = std::env::args().collect::<Vec<String>>();
@@ -41,24 +74,33 @@ fn main() -> std::io::Result<()> {
         Ok(())
     } else {
         let cmd = args.remove(0);
+        let policy = if cmd == "serve" {
+            take_flag(&mut args, "--policy")
+        } else {
+            None
+        };
         match (cmd.as_str(), args.as_slice()) {
             ("--help", []) => {
                 help();
                 Ok(())
             }
             ("test", [path]) => run::run_test(path),
+            ("builtins", []) => {
+                builtins();
+                Ok(())
+            }
             // ("runtime_tests", [path]) => run::runtime_tests(path),
-            ("serve", []) => server::serve(crate::pack::default_root(), None),
+            ("serve", []) => server::serve(crate::pack::default_root(), None, policy),
             ("serve", [overridez, op]) if overridez == "--override" => {
-                server::serve(crate::pack::default_root(), Some(op.to_owned()))
+                server::serve(crate::pack::default_root(), Some(op.to_owned()), policy)
             }
-            ("serve", [root]) => server::serve(std::path::PathBuf::from(root), None),
+            ("serve", [root]) => server::serve(std::path::PathBuf::from(root), None, policy),
             // lol maybe I should get real arg parsing
             ("serve", [root, overridez, op]) if overridez == "--override" => {
-                server::serve(std::path::PathBuf::from(root), Some(op.to_owned()))
+                server::serve(std::path::PathBuf::from(root), Some(op.to_owned()), policy)
             }
             ("serve", [overridez, op, root]) if overridez == "--override" => {
-                server::serve(std::path::PathBuf::from(root), Some(op.to_owned()))
+                server::serve(std::path::PathBuf::from(root), Some(op.to_owned()), policy)
             }
             ("pack-watch", [path, output]) => pack::pack_watch(path, output),
             ("pack", [path, output]) => pack::pack(path, output),
@@ -74,7 +116,7 @@ fn main() -> std::io::Result<()> {
             ("pack-all-json-watch", args) => pack::pack_all_json_watch(&args[1..], &args[0]),
             ("pack-all", [path, output]) => pack::pack_all(&std::path::PathBuf::from(path), output),
             // ("test-all", [path]) => run_all_tests(path),
-            ("run", args) => run::run_cli_term(&args[0], &args[1..]),
+            ("run", args) => run::run_cli(args),
             _ => {
                 println!("Unknown invocation.");
                 help();
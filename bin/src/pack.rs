@@ -83,7 +83,12 @@ pub fn pack_all(terms_path: &std::path::Path, out: &str) -> std::io::Result<()>
     hashes.sort();
     for hash in hashes {
         println!("Loading {:?}", hash);
-        let _ = ir_env.load(hash);
+        if let Err(env::Error::UnknownBuiltin(name)) = ir_env.load(hash) {
+            println!(
+                "Unable to pack {:?} - uses unsupported builtin {}",
+                hash, name
+            );
+        }
     }
 
     {
@@ -185,7 +190,7 @@ pub fn terms_to_env(root: &std::path::Path, hashes: Vec<Id>) -> std::io::Result<
     let env = env::Env::init(&root);
     let mut ir_env = ir::TranslationEnv::new(env);
     for hash in hashes {
-        ir_env.load(&hash).unwrap();
+        ir_env.load(&hash)?;
     }
 
     walk_env(&mut ir_env.env);
@@ -196,7 +201,7 @@ pub fn terms_to_env(root: &std::path::Path, hashes: Vec<Id>) -> std::io::Result<
 pub fn term_to_env(root: &std::path::Path, hash: &str) -> std::io::Result<types::RuntimeEnv> {
     let env = env::Env::init(&root);
     let mut ir_env = ir::TranslationEnv::new(env);
-    ir_env.load(&types::Id::from_string(hash)).unwrap();
+    ir_env.load(&types::Id::from_string(hash))?;
 
     walk_env(&mut ir_env.env);
 
@@ -215,6 +220,7 @@ impl JsonEnv {
             terms,
             types,
             anon_fns,
+            ..
         }: RuntimeEnv,
     ) -> JsonEnv {
         use std::iter::FromIterator;
@@ -596,7 +602,12 @@ fn pack_all_json_inner(
     let mut ir_env = ir::TranslationEnv::new(env);
 
     for hash in all_terms.values() {
-        let _ = ir_env.load(hash);
+        if let Err(env::Error::UnknownBuiltin(name)) = ir_env.load(hash) {
+            println!(
+                "Unable to pack {:?} - uses unsupported builtin {}",
+                hash, name
+            );
+        }
     }
 
     {
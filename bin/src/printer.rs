@@ -43,13 +43,25 @@ pub fn unwrap_tuple(value: &Value) -> Vec<&Value> {
 
 use std::collections::HashMap;
 
-#[derive(Default)]
+// The way unison writes a bytes literal
+fn bytes_literal(bytes: &im::Vector<u8>) -> String {
+    let hex: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("0xs{}", hex.join(""))
+}
+
+#[derive(Default, Clone)]
 pub struct FlatNames {
     pub terms: HashMap<String, Vec<String>>,
     pub types: HashMap<String, Vec<String>>,
     pub constructors: HashMap<(String, usize), Vec<String>>,
 }
 
+impl FlatNames {
+    pub fn term_name(&self, id: &Id) -> Option<String> {
+        self.terms.get(&id.to_string()).map(|name| name.join("."))
+    }
+}
+
 fn value_to_doc(value: &Value, names: &FlatNames) -> pretty::RcDoc<'static, ()> {
     use Value::*;
     match value {
@@ -117,7 +129,7 @@ fn value_to_doc(value: &Value, names: &FlatNames) -> pretty::RcDoc<'static, ()>
         Float(i) => RcDoc::as_string(i),
         Text(i) => RcDoc::as_string(i),
         Char(i) => RcDoc::as_string(i),
-        Bytes(_) => RcDoc::as_string("<Bytes>"),
+        Bytes(b) => RcDoc::as_string(bytes_literal(b)),
         Ref(_) => RcDoc::as_string("<Ref>"),
         x => RcDoc::as_string(format!("{:?}", x)),
     }
@@ -173,7 +185,14 @@ impl ToDoc for Pattern {
                     .group(),
                 )
                 .append(RcDoc::text("]")),
-            SequenceOp(_a, _op, _b) => RcDoc::text("seq op"),
+            SequenceOp(a, op, b) => a
+                .to_doc(names)
+                .append(RcDoc::text(match op {
+                    SeqOp::Cons => " +: ",
+                    SeqOp::Snoc => " :+ ",
+                    SeqOp::Concat => " ++ ",
+                }))
+                .append(b.to_doc(names)),
         }
     }
 }
@@ -288,7 +307,7 @@ impl ToDoc for Term {
             Float(i) => RcDoc::as_string(i),
             Boolean(i) => RcDoc::as_string(i),
             Text(i) => RcDoc::text(format!("{:?}", i)),
-            Bytes(_) => RcDoc::text("<bytes>"),
+            Bytes(b) => RcDoc::text(bytes_literal(b)),
             Char(i) => RcDoc::as_string(i),
             Blank => RcDoc::text("<blank>"),
             // PartialNativeApp(name, _) => f.write_fmt(format_args!("partial({})", name)),
@@ -11,6 +11,9 @@ impl shared::convert::ConvertibleArg<WrappedValue> for WrappedValue {
     fn as_f64(&self) -> Option<f64> {
         self.0.parse().ok()
     }
+    fn as_bool(&self) -> Option<bool> {
+        self.0.parse().ok()
+    }
     fn as_string(&self) -> Option<String> {
         self.0.parse().ok()
     }
@@ -22,7 +25,179 @@ impl shared::convert::ConvertibleArg<WrappedValue> for WrappedValue {
     }
 }
 
-pub fn run_cli_term(term: &String, args: &[String]) -> std::io::Result<()> {
+// `run [--max-steps n] [--timeout-ms n] [--policy file.json] [--allow op] [--deny op]
+//      [--allow-dir path] [--max-calls n] [--json] term ...args`
+pub fn run_cli(args: &[String]) -> std::io::Result<()> {
+    let mut budget = shared::budget::Budget::unlimited();
+    let mut timeout = None;
+    let mut policy = shared::policy::Policy::default();
+    let mut json = false;
+    let mut args = args;
+    loop {
+        match args {
+            [flag, rest @ ..] if flag == "--json" => {
+                json = true;
+                args = rest;
+            }
+            [flag, n, rest @ ..] if flag == "--max-steps" => {
+                budget.steps = Some(parse_flag(flag, n)?);
+                args = rest;
+            }
+            [flag, n, rest @ ..] if flag == "--timeout-ms" => {
+                timeout = Some(std::time::Duration::from_millis(parse_flag(flag, n)?));
+                args = rest;
+            }
+            [flag, path, rest @ ..] if flag == "--policy" => {
+                policy = load_policy(path)?;
+                args = rest;
+            }
+            [flag, rule, rest @ ..] if flag == "--allow" => {
+                policy
+                    .allow
+                    .get_or_insert_with(Vec::new)
+                    .push(rule.to_owned());
+                args = rest;
+            }
+            [flag, rule, rest @ ..] if flag == "--deny" => {
+                policy.deny.push(rule.to_owned());
+                args = rest;
+            }
+            [flag, path, rest @ ..] if flag == "--allow-dir" => {
+                policy
+                    .directories
+                    .get_or_insert_with(Vec::new)
+                    .push(path.into());
+                args = rest;
+            }
+            [flag, n, rest @ ..] if flag == "--max-calls" => {
+                policy.max_calls = Some(parse_flag(flag, n)?);
+                args = rest;
+            }
+            _ => break,
+        }
+    }
+    match args {
+        [term, rest @ ..] => run_cli_term(term, rest, budget, timeout, policy, json),
+        [] => Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "No term provided to run",
+        )),
+    }
+}
+
+fn is_unit(typ: &types::ABT<types::Type>) -> bool {
+    match typ {
+        types::ABT::Tm(types::Type::Ref(types::Reference::DerivedId(id))) => {
+            id.hash.0 == shared::convert::UNIT_HASH
+        }
+        _ => false,
+    }
+}
+
+// A json file like `{"allow": ["native.IO"], "deny": ["native.IO.removeFile"],
+// "directories": ["./data"], "max_calls": 1000}`. Every field is optional.
+pub fn load_policy(path: &str) -> std::io::Result<shared::policy::Policy> {
+    let raw = std::fs::read_to_string(path)?;
+    serde_json::from_str(&raw).map_err(|err| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("Invalid policy file {}: {}", path, err),
+        )
+    })
+}
+
+// Record field names, for the JSON codec. Each field of a record gets `Type.field`,
+// `Type.field.set` & `Type.field.modify` accessors, and the getter's pattern match
+// tells us which field it is.
+fn record_fields(
+    root: &std::path::Path,
+    names: &crate::pack::Names<types::Id>,
+    runtime_env: &types::RuntimeEnv,
+) -> std::collections::HashMap<types::Reference, Vec<String>> {
+    let mut by_name = std::collections::HashMap::new();
+    for (id, names) in names.terms.iter() {
+        for name in names {
+            by_name.insert(name.join("."), id);
+        }
+    }
+    let mut env = env::Env::init(root);
+    let mut fields = std::collections::HashMap::new();
+    for (id, decl) in runtime_env.types.iter() {
+        let count = match (decl, names.types.get(id)) {
+            (types::TypeDecl::Data(data), Some(_)) if data.constructors.len() == 1 => {
+                let (_, typ) = &data.constructors[0];
+                typ.args_and_effects().0.len()
+            }
+            _ => continue,
+        };
+        for type_name in names.types[id].iter().map(|name| name.join(".")) {
+            let mut found = vec![None; count];
+            for (name, getter) in by_name.iter() {
+                let field = match name.strip_prefix(&type_name) {
+                    Some(rest) if rest.starts_with('.') && !rest[1..].contains('.') => &rest[1..],
+                    _ => continue,
+                };
+                if !by_name.contains_key(&format!("{}.set", name)) {
+                    continue;
+                }
+                if let Ok((term, _)) = env.load(&getter.to_string()) {
+                    if let Some(idx) = field_index(&term).filter(|idx| *idx < count) {
+                        found[idx] = Some(field.to_owned());
+                    }
+                }
+            }
+            if count > 0 && found.iter().all(Option::is_some) {
+                fields.insert(
+                    types::Reference::DerivedId(id.clone()),
+                    found.into_iter().map(Option::unwrap).collect(),
+                );
+                break;
+            }
+        }
+    }
+    fields
+}
+
+// For a getter like `Point.x = cases Point x _ -> x`, the position of the `x`.
+fn field_index(term: &types::ABT<types::Term>) -> Option<usize> {
+    use types::{Pattern, Term, ABT};
+    match term {
+        ABT::Abs(_, _, inner) | ABT::Cycle(inner) => field_index(inner),
+        ABT::Tm(Term::Lam(inner, _)) | ABT::Tm(Term::Ann(inner, _)) => field_index(inner),
+        ABT::Tm(Term::Match(_, cases)) => match cases.as_slice() {
+            [types::MatchCase(Pattern::Constructor(_, 0, patterns), None, _)] => {
+                let mut vars = patterns
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, pattern)| **pattern == Pattern::Var);
+                match (vars.next(), vars.next()) {
+                    (Some((idx, _)), None) => Some(idx),
+                    _ => None,
+                }
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn parse_flag<T: std::str::FromStr>(flag: &str, value: &str) -> std::io::Result<T> {
+    value.parse().map_err(|_| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("Invalid value for {}: {}", flag, value),
+        )
+    })
+}
+
+pub fn run_cli_term(
+    term: &String,
+    args: &[String],
+    mut budget: shared::budget::Budget,
+    timeout: Option<std::time::Duration>,
+    policy: shared::policy::Policy,
+    json: bool,
+) -> std::io::Result<()> {
     let project = crate::pack::default_root();
 
     let terms_path = {
@@ -67,11 +242,11 @@ pub fn run_cli_term(term: &String, args: &[String]) -> std::io::Result<()> {
                 &term[1..].split(".").collect::<Vec<&str>>().as_slice(),
             )
             .unwrap();
-        ir_env.load(&hash).unwrap();
+        ir_env.load(&hash)?;
         hash
     } else {
         let hash = types::Id::from_string(term);
-        ir_env.load(&hash).unwrap();
+        ir_env.load(&hash)?;
         hash
     };
 
@@ -80,19 +255,26 @@ pub fn run_cli_term(term: &String, args: &[String]) -> std::io::Result<()> {
 
     let mut runtime_env: shared::types::RuntimeEnv = ir_env.into();
 
+    // These go to stderr, so that stdout is left to the program
     let t = &runtime_env.terms.get(&hash).unwrap().1;
-    println!("Type: {:?}", t);
+    eprintln!("Type: {:?}", t);
     let (targs, effects, tres) = shared::ir_runtime::extract_args(t);
 
-    let args = shared::convert::convert_args(
-        args.into_iter().map(|x| WrappedValue(x.clone())).collect(),
-        &targs,
-    )
-    .unwrap();
-    println!("Got {:?} -- {:?} -- {:?}", targs, effects, tres);
+    // A delayed computation like `'{native.IO} ()` is called with `()`, and the
+    // command-line args are left for it to get with `native.IO.getArgs`
+    let term_args = match targs.as_slice() {
+        [arg] if is_unit(arg) => vec![shared::unit()],
+        _ => shared::convert::convert_args(
+            &runtime_env,
+            args.into_iter().map(|x| WrappedValue(x.clone())).collect(),
+            &targs,
+        )
+        .unwrap(),
+    };
+    eprintln!("Got {:?} -- {:?} -- {:?}", targs, effects, tres);
 
-    let run_hash = if args.len() > 0 {
-        runtime_env.add_eval(&hash.to_string(), args).unwrap()
+    let run_hash = if term_args.len() > 0 {
+        runtime_env.add_eval(&hash.to_string(), term_args).unwrap()
     } else {
         hash
     };
@@ -101,7 +283,23 @@ pub fn run_cli_term(term: &String, args: &[String]) -> std::io::Result<()> {
 
     let mut names = Default::default();
     branch.get_flat_names(&vec![], &mut names);
-    let mut ffi = ffi::RustFFI(names, vec![]);
+    let codebase_names = crate::branch::Codebase::new(root.to_owned())?.get_names();
+    let abilities = ffi::ability_names(&codebase_names);
+    let io = crate::io::NativeIO::new(&runtime_env, args.to_vec())?;
+    let mut ffi = crate::io::sandbox(
+        ffi::RustFFI::new(names, io, abilities),
+        policy,
+        &runtime_env,
+    );
+    // Requests with future handlers are awaited on this
+    let mut rt = tokio::runtime::Runtime::new()?;
+    // Waiting doesn't need to tie up the thread here
+    let wait = ffi.inner.3.add_future("Wait", 0, |millis: u64| {
+        tokio::time::delay_for(std::time::Duration::from_millis(millis))
+    });
+    if let Err(message) = wait {
+        log::info!("Waiting will block the thread: {}", message);
+    }
 
     // for effect in effects {
     //     use shared::ffi::FFI;
@@ -114,22 +312,54 @@ pub fn run_cli_term(term: &String, args: &[String]) -> std::io::Result<()> {
         &runtime_env,
         run_hash,
         false,
-        shared::state::build_effects_map(effects),
+        shared::state::build_effects_map(effects).map_err(|error| {
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, error.to_string())
+        })?,
     );
-    println!("[---running---]");
-    let ret = state
-        .run_to_end(&mut ffi, &mut trace)
-        .expect("Invalid FFI I guess");
-    match ret {
-        None => (),
-        Some(ret) => println!("-> {}", crate::printer::value_to_pretty(&ret, &ffi.0, 100)),
+    // The clock starts when the program does, not while the codebase is loading
+    if let Some(timeout) = timeout {
+        budget.deadline = shared::budget::Budget::timeout(timeout).deadline;
+    }
+    state.budget = budget.clone();
+    eprintln!("[---running---]");
+    let names = ffi.inner.0.clone();
+    let mut codec = shared::json::Codec::new(&runtime_env);
+    if json {
+        codec.fields = record_fields(root, &codebase_names, &runtime_env);
+    }
+    let print_result = |ret: std::sync::Arc<types::Value>| {
+        if json {
+            match codec.to_json(&tres, &ret) {
+                Ok(json) => println!("{}", json),
+                Err(err) => eprintln!("Unable to encode the result as JSON: {}", err),
+            }
+        } else if *ret != shared::unit() {
+            println!("-> {}", crate::printer::value_to_pretty(&ret, &names, 100))
+        }
     };
-
-    while ffi.has_next_request() {
-        println!("> Async handler");
-        ffi.process_next_request(&runtime_env, &mut trace)
-            .expect("Invalid FFI Response");
+    let ret = rt
+        .block_on(shared::concurrent::run_to_end(
+            &runtime_env,
+            &mut state,
+            &mut ffi,
+            &mut trace,
+        ))
+        .map_err(|failure| exit_or_fail(failure, &names))?;
+    if let Some(ret) = ret {
+        print_result(ret);
     }
+
+    // Any requests that couldn't be handled synchronously
+    crate::event_loop::run(
+        &mut rt,
+        &mut ffi,
+        &runtime_env,
+        &budget,
+        state.steps,
+        &mut trace,
+        print_result,
+    )
+    .map_err(|failure| exit_or_fail(failure, &names))?;
     // let ret = shared::ir_runtime::eval(&runtime_env, eval_hash, &mut trace);
 
     // std::fs::File::create("trace.json");
@@ -142,15 +372,54 @@ pub fn run_cli_term(term: &String, args: &[String]) -> std::io::Result<()> {
     Ok(())
 }
 
+// Exits the process if the program asked to, with the code it gave.
+fn exit_or_fail(
+    failure: shared::error::Failure,
+    names: &crate::printer::FlatNames,
+) -> std::io::Error {
+    if let shared::error::RuntimeError::Exit(code) = *failure.error {
+        use std::io::Write;
+        let _ = std::io::stdout().flush();
+        std::process::exit(code)
+    }
+    runtime_failure(failure, names)
+}
+
+fn runtime_failure(
+    failure: shared::error::Failure,
+    names: &crate::printer::FlatNames,
+) -> std::io::Error {
+    use shared::error::RuntimeError;
+    let message = match &*failure.error {
+        RuntimeError::Bug(value) => format!(
+            "bug: {}",
+            crate::printer::value_to_pretty(value, names, 100)
+        ),
+        RuntimeError::Todo(value) => format!(
+            "todo: {}",
+            crate::printer::value_to_pretty(value, names, 100)
+        ),
+        error => error.to_string(),
+    };
+    eprintln!(
+        "Runtime error: {}\n{}",
+        message,
+        failure.trace.render(|id| names.term_name(id))
+    );
+    std::io::Error::new(std::io::ErrorKind::Other, message)
+}
+
 pub fn run_term(
     terms_path: &std::path::Path,
     hash: &str,
-) -> std::io::Result<std::sync::Arc<types::Value>> {
+    names: &crate::printer::FlatNames,
+    abilities: std::collections::HashMap<String, types::Reference>,
+) -> std::io::Result<Result<std::sync::Arc<types::Value>, shared::error::Failure>> {
     let last = std::time::Instant::now();
     println!("Running {:?} - {}", terms_path, hash);
     let env = env::Env::init(terms_path.parent().unwrap());
     let mut ir_env = ir::TranslationEnv::new(env);
-    ir_env.load(&types::Id::from_string(hash)).unwrap();
+    ir_env.load(&types::Id::from_string(hash))?;
     use crate::printer::ToPretty;
 
     {
@@ -189,9 +458,8 @@ pub fn run_term(
     let runtime_env: shared::types::RuntimeEnv = ir_env.into();
 
     let mut trace = shared::chrome_trace::Traces::new();
-    let names = Default::default();
-    // branch.get_flat_names(&vec![], &mut names);
-    let mut ffi = ffi::RustFFI(names, vec![]);
+    let io = crate::io::NativeIO::new(&runtime_env, vec![])?;
+    let mut ffi = ffi::RustFFI::new(names.clone(), io, abilities);
     let ret = shared::ir_runtime::eval(
         &runtime_env,
         &mut ffi,
@@ -200,8 +468,7 @@ pub fn run_term(
         true,
         Default::default(),
     )
-    .expect("Invalid FFI")
-    .unwrap();
+    .map(|ret| ret.unwrap());
     println!(
         "Time: {}ms ({}ns)",
         last.elapsed().as_millis(),
@@ -252,6 +519,10 @@ pub fn run_test(root: &str) -> std::io::Result<()> {
     let mut branch = Branch::load(&paths, get_head(&paths)?)?;
     branch.load_children(&paths, true)?;
 
+    let mut names = Default::default();
+    branch.get_flat_names(&vec![], &mut names);
+    let abilities = ffi::ability_names(&crate::branch::Codebase::new(root.clone())?.get_names());
+
     let terms = path_with(&root, "terms");
     let mut all_terms = std::collections::HashMap::new();
     branch.collect_terms(&vec![], &mut all_terms);
@@ -264,7 +535,14 @@ pub fn run_test(root: &str) -> std::io::Result<()> {
             }
             println!("--> {:?}", k.join("."));
             let hash = all_terms.get(&k).unwrap().to_string();
-            let ret = run_term(&terms, &hash)?;
+            let ret = match run_term(&terms, &hash, &names, abilities.clone())? {
+                Ok(ret) => ret,
+                Err(failure) => {
+                    runtime_failure(failure, &names);
+                    println!("Test {} failed!", hash);
+                    return Ok(());
+                }
+            };
             use types::*;
             match &*ret {
                 Value::Sequence(results) => {
@@ -381,7 +659,10 @@ pub fn run(file: &String) -> std::io::Result<()> {
         let ret = run_term(
             path.parent().unwrap(),
             &path.file_name().unwrap().to_str().unwrap()[1..],
-        )?;
+            &Default::default(),
+            Default::default(),
+        )?
+        .map_err(|failure| runtime_failure(failure, &Default::default()))?;
         println!("-> {:?}", ret);
         return Ok(());
     }
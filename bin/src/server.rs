@@ -147,7 +147,11 @@ fn mime_for_ext(f: &str) -> &'static str {
     }
 }
 
-async fn main(codebase_root: std::path::PathBuf, override_dir: Option<String>) {
+async fn main(
+    codebase_root: std::path::PathBuf,
+    override_dir: Option<String>,
+    policy: shared::policy::Policy,
+) {
     let pool_ref = PoolRef::default();
     let pool_for_head_message = pool_ref.clone();
 
@@ -265,6 +269,12 @@ async fn main(codebase_root: std::path::PathBuf, override_dir: Option<String>) {
         .and(codebase_for_warp)
         .and_then(|hash, ns, codebase| serve_terms(hash, ns, codebase));
 
+    // /policy, for the client to run terms with
+    let policy = serde_json::to_string_pretty(&policy).unwrap();
+    let policy = warp::path("policy")
+        .and(warp::path::end())
+        .map(move || policy.clone());
+
     // So I think I'll be spawning a thread for the notify thing?
 
     let pool = warp::any().map(move || pool_ref.clone());
@@ -289,7 +299,8 @@ async fn main(codebase_root: std::path::PathBuf, override_dir: Option<String>) {
         .or(json)
         .or(terms)
         .or(root_terms)
-        .or(info);
+        .or(info)
+        .or(policy);
 
     println!("Serving at http://127.0.0.1:3030");
     // If specified, serve files from the override_dir first (for developing the client code, or providing custom handlers)
@@ -359,13 +370,13 @@ async fn serve_terms(
 
         let (args, effects, _res) = typ.args_and_effects();
 
-        let all_primitive = args.iter().all(|m| m.is_primitive());
+        let all_convertible = args.iter().all(|m| m.is_convertible());
 
         use crate::printer::ToPretty;
         typed_terms.push((
             name,
             typ.to_pretty(100, &flat_names),
-            if all_primitive {
+            if all_convertible {
                 Some((
                     args.into_iter()
                         .map(|m| shared::convert::to_json_type(m))
@@ -434,8 +445,13 @@ async fn serve_bin(
 pub fn serve(
     codebase_root: std::path::PathBuf,
     override_dir: Option<String>,
+    policy: Option<String>,
 ) -> std::io::Result<()> {
+    let policy = match policy {
+        Some(path) => crate::run::load_policy(&path)?,
+        None => Default::default(),
+    };
     let mut rt = tokio::runtime::Runtime::new().unwrap();
-    rt.block_on(main(codebase_root, override_dir));
+    rt.block_on(main(codebase_root, override_dir, policy));
     Ok(())
 }
@@ -63,7 +63,8 @@ const App = () => {
         const namesUrl = fetch(
             `/build/${head}/${watchers.map((m) => '.' + m).join(',')}/names`,
         ).then((r) => r.json());
-        loadRuntime(dataUrl, namesUrl).then((runtime) =>
+        const policy = fetch('/policy').then((r) => (r.ok ? r.json() : null));
+        loadRuntime(dataUrl, namesUrl, policy).then((runtime) =>
             setState((state) => {
                 console.log('loaded new runtime');
                 if (state.head !== head) {
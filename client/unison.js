@@ -73,7 +73,9 @@ const convertHandlers = (handlers, handlerHashes) => {
         Object.keys(handlers[abilityName]).forEach((constrName) => {
             const idx = handlerHashes[abilityName].idxs[constrName];
             const v = handlers[abilityName][constrName];
-            if (v.type === 'full') {
+            // `async` handlers get just the arguments, and can return a Promise
+            // (only for `runAsync`)
+            if (v.type === 'full' || v.type === 'async') {
                 res.push([hash, +idx, false, v.handler]);
             } else {
                 res.push([hash, +idx, true, v]);
@@ -146,12 +148,18 @@ export const fetch = (dataUrl, namesUrl) => {
     );
 };
 
-export const load = async (dataPromise, namesPromise) => {
+// policyPromise is optional, and resolves to the capability policy that
+// runs should be checked against (see `unison.rs serve --policy`).
+export const load = async (dataPromise, namesPromise, policyPromise) => {
     const jsBridge = await js;
     const data = await dataPromise;
     const names = await namesPromise;
+    const policy = await policyPromise;
     // console.log('have data', data.slice(0, 100));
     const id = jsBridge.load(data);
+    if (policy) {
+        jsBridge.set_policy(id, policy);
+    }
     const hashesByName = hashesForConstrName(names);
     const hashesByTermName = hashesForTermName(names);
 
@@ -184,6 +192,14 @@ export const load = async (dataPromise, namesPromise) => {
             const converted = convert_handlers(handlers, hashesByName, names);
             return jsBridge.run(id, hash, args, converted);
         },
+        runAsync: (term, args, handlers) => {
+            return jsBridge.run_async(
+                id,
+                getHash(term),
+                args,
+                convert_handlers(handlers, hashesByName, names),
+            );
+        },
         runSync: (term, args, handlers) => {
             return jsBridge.run_sync(
                 id,
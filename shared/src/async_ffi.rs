@@ -0,0 +1,105 @@
+// Handling ability requests with futures, so hosts can call async APIs without
+// blocking. The State bails out on the request, we await the handler's future,
+// and then pick the State back up with the result.
+//
+// Nothing here depends on a particular executor: bin runs it with tokio, and
+// wasm with `wasm-bindgen-futures`.
+use super::chrome_trace::Traces;
+use super::error::{Failure, RuntimeError};
+use super::ffi::FFI;
+use super::state::{FullRequest, State, Step};
+use super::types::*;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+// Resolves to the value to resume with, or the reason the handler failed.
+pub type ValueFuture = Pin<Box<dyn Future<Output = Result<Value, String>>>>;
+
+pub trait AsyncFFI: FFI {
+    // Called for the requests that `handle_request_sync` bailed out on.
+    // `None` means it isn't handled asynchronously either, and it gets
+    // passed on to `handle_request`.
+    // As with `handle_request_sync`, the FFI is responsible for the type of the value.
+    fn handle_request_async(
+        &mut self,
+        typ: &ABT<Type>,
+        kind: &Reference,
+        number: usize,
+        args: &Vec<Arc<Value>>,
+    ) -> Option<ValueFuture>;
+}
+
+// How many instructions to run before letting other futures have a turn
+pub const SLICE: usize = 10_000;
+
+// Like `State::run_to_end`, except that requests handled by `handle_request_async`
+// are awaited, instead of ending the run. Afterwards, the state's `steps` include
+// the ones every resumption used.
+pub async fn run_to_end<'a, T: AsyncFFI>(
+    state: &mut State<'a>,
+    ffi: &mut T,
+    trace: &mut Traces,
+) -> Result<Option<Arc<Value>>, Failure> {
+    loop {
+        let request = match state.step(ffi, trace, SLICE)? {
+            Step::Done(value) => return Ok(Some(value)),
+            Step::Suspended(_) => {
+                yield_now().await;
+                continue;
+            }
+            Step::Request(request) => request,
+        };
+        let future = match ffi.handle_request_async(&request.5, &request.0, request.1, &request.2) {
+            Some(future) => future,
+            None => {
+                ffi.handle_request(request);
+                return Ok(None);
+            }
+        };
+        let FullRequest(kind, number, _args, frames, final_index, _t) = request;
+        let value = match future.await {
+            Ok(value) => value,
+            Err(message) => {
+                return Err(Failure::new(
+                    RuntimeError::AsyncFailure(kind, number, message),
+                    &frames,
+                    final_index,
+                ))
+            }
+        };
+        let (budget, steps) = (state.budget.clone(), state.steps);
+        *state = State::full_resume(
+            state.env,
+            kind,
+            number,
+            frames,
+            final_index,
+            Arc::new(value),
+        )?;
+        state.budget = budget;
+        state.steps = steps;
+    }
+}
+
+// Gives the executor a chance to run something else.
+fn yield_now() -> YieldNow {
+    YieldNow(false)
+}
+
+struct YieldNow(bool);
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        if self.0 {
+            Poll::Ready(())
+        } else {
+            self.0 = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
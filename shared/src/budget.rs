@@ -0,0 +1,50 @@
+// How much work a State is allowed to do before bailing out with `OutOfFuel`.
+// The default is unlimited.
+#[derive(Debug, Clone, Default)]
+pub struct Budget {
+    // max number of instructions to execute
+    pub steps: Option<usize>,
+    // wall-clock deadline. Not available on wasm, where `Instant` isn't supported.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub deadline: Option<std::time::Instant>,
+}
+
+impl Budget {
+    pub fn unlimited() -> Self {
+        Default::default()
+    }
+
+    pub fn steps(steps: usize) -> Self {
+        Budget {
+            steps: Some(steps),
+            ..Default::default()
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn timeout(duration: std::time::Duration) -> Self {
+        Budget {
+            deadline: Some(std::time::Instant::now() + duration),
+            ..Default::default()
+        }
+    }
+
+    // `used` is the number of instructions executed so far.
+    pub fn is_exhausted(&self, used: usize) -> bool {
+        if let Some(steps) = self.steps {
+            if used >= steps {
+                return true;
+            }
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            // Checking the clock is comparatively expensive
+            if let Some(deadline) = self.deadline {
+                if used % 100 == 0 && std::time::Instant::now() >= deadline {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+}
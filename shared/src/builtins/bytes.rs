@@ -0,0 +1,374 @@
+use super::BuiltinRegistry;
+use crate::types::Value;
+use im::Vector;
+use std::sync::Arc;
+
+const BASE32: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+fn to_vec(bytes: &Vector<u8>) -> Vec<u8> {
+    bytes.iter().cloned().collect()
+}
+
+fn to_base16(bytes: &[u8]) -> Vec<u8> {
+    bytes
+        .iter()
+        .flat_map(|b| format!("{:02x}", b).into_bytes())
+        .collect()
+}
+
+fn from_base16(text: &[u8]) -> Option<Vec<u8>> {
+    if text.len() % 2 != 0 {
+        return None;
+    }
+    text.chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
+
+// RFC 4648, with padding
+fn to_base32(bytes: &[u8]) -> Vec<u8> {
+    let mut out = vec![];
+    for chunk in bytes.chunks(5) {
+        let mut buf = [0u8; 5];
+        buf[..chunk.len()].copy_from_slice(chunk);
+        let n = buf.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64);
+        let chars = (chunk.len() * 8 + 4) / 5;
+        for i in 0..8 {
+            out.push(if i < chars {
+                BASE32[((n >> (35 - i * 5)) & 31) as usize]
+            } else {
+                b'='
+            });
+        }
+    }
+    out
+}
+
+fn from_base32(text: &[u8]) -> Option<Vec<u8>> {
+    if text.len() % 8 != 0 {
+        return None;
+    }
+    let mut out = vec![];
+    let chunks = text.len() / 8;
+    for (i, chunk) in text.chunks(8).enumerate() {
+        let chars = chunk.iter().take_while(|c| **c != b'=').count();
+        // Padding is only allowed at the very end
+        if chars < 8 && i + 1 < chunks {
+            return None;
+        }
+        if chunk[chars..].iter().any(|c| *c != b'=') {
+            return None;
+        }
+        let bytes = match chars {
+            2 => 1,
+            4 => 2,
+            5 => 3,
+            7 => 4,
+            8 => 5,
+            _ => return None,
+        };
+        let mut n = 0u64;
+        for i in 0..8 {
+            let digit = if i < chars {
+                BASE32.iter().position(|c| *c == chunk[i])?
+            } else {
+                0
+            };
+            n = (n << 5) | digit as u64;
+        }
+        for i in 0..bytes {
+            out.push((n >> (32 - i * 8)) as u8);
+        }
+    }
+    Some(out)
+}
+
+// The lowest `width` bytes of `n`
+fn encode_nat(n: u64, width: usize, big_endian: bool) -> Vector<u8> {
+    let bytes = &n.to_be_bytes()[8 - width..];
+    if big_endian {
+        bytes.iter().cloned().collect()
+    } else {
+        bytes.iter().rev().cloned().collect()
+    }
+}
+
+// The number, and the rest of the bytes
+fn decode_nat(bytes: &Vector<u8>, width: usize, big_endian: bool) -> Option<(u64, Vector<u8>)> {
+    if bytes.len() < width {
+        return None;
+    }
+    let (number, rest) = bytes.clone().split_at(width);
+    let number: Vec<u8> = if big_endian {
+        number.into_iter().collect()
+    } else {
+        number.into_iter().rev().collect()
+    };
+    Some((
+        number.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64),
+        rest,
+    ))
+}
+
+macro_rules! nat_codec {
+    ($registry:expr, $encode:expr, $decode:expr, $width:expr, $big_endian:expr) => {
+        builtin!($registry, $encode, "Nat -> Bytes", |Value::Nat(n)| Ok(
+            Value::Bytes(encode_nat(*n, $width, $big_endian))
+        ));
+        builtin!(
+            $registry,
+            $decode,
+            "Bytes -> Optional (Nat, Bytes)",
+            refs => |Value::Bytes(b)| Ok(match decode_nat(b, $width, $big_endian) {
+                Some((n, rest)) => refs.some(Arc::new(refs.tuple(vec![
+                    Arc::new(Value::Nat(n)),
+                    Arc::new(Value::Bytes(rest)),
+                ]))),
+                None => refs.none(),
+            })
+        );
+    };
+}
+
+// The decoders give a `Left` with the error message for invalid input
+macro_rules! base_codec {
+    ($registry:expr, $to:expr, $from:expr, $encode:expr, $decode:expr) => {
+        builtin!($registry, $to, "Bytes -> Bytes", |Value::Bytes(b)| Ok(
+            Value::Bytes($encode(&to_vec(b)).into_iter().collect())
+        ));
+        builtin!(
+            $registry,
+            $from,
+            "Bytes -> Either Text Bytes",
+            refs => |Value::Bytes(b)| match $decode(&to_vec(b)) {
+                Some(bytes) => refs.right(Arc::new(Value::Bytes(bytes.into_iter().collect()))),
+                None => refs.left(Arc::new(Value::Text(
+                    format!("Invalid input for {}", $from).into(),
+                ))),
+            }
+        );
+    };
+}
+
+pub fn register(registry: &mut BuiltinRegistry) {
+    builtin!(registry, "Bytes.empty", "Bytes", || Ok(Value::Bytes(
+        Vector::new()
+    )));
+    builtin!(registry, "Bytes.size", "Bytes -> Nat", |Value::Bytes(t)| {
+        Ok(Value::Nat(t.len() as u64))
+    });
+    builtin!(
+        registry,
+        "Bytes.toList",
+        "Bytes -> [Nat]",
+        |Value::Bytes(t)| {
+            Ok(Value::Sequence(
+                t.iter().map(|t| Arc::new(Value::Nat(*t as u64))).collect(),
+            ))
+        }
+    );
+    // Like the haskell runtime, each Nat is truncated to a byte
+    builtin!(
+        registry,
+        "Bytes.fromList",
+        "[Nat] -> Bytes",
+        |Value::Sequence(l)| {
+            Ok(Value::Bytes(
+                l.iter()
+                    .map(|n| match &**n {
+                        Value::Nat(n) => Ok(*n as u8),
+                        v => Err(crate::error::RuntimeError::UnexpectedValue(
+                            "Nat".to_owned(),
+                            Box::new(v.clone()),
+                        )),
+                    })
+                    .collect::<Result<Vector<u8>, _>>()?,
+            ))
+        }
+    );
+    builtin!(
+        registry,
+        "Bytes.++",
+        "Bytes -> Bytes -> Bytes",
+        |Value::Bytes(a), Value::Bytes(b)| {
+            let mut a = a.clone();
+            a.append(b.clone());
+            Ok(Value::Bytes(a))
+        }
+    );
+    builtin!(
+        registry,
+        "Bytes.take",
+        "Nat -> Bytes -> Bytes",
+        |Value::Nat(n), Value::Bytes(b)| { Ok(Value::Bytes(b.take((*n as usize).min(b.len())))) }
+    );
+    builtin!(
+        registry,
+        "Bytes.drop",
+        "Nat -> Bytes -> Bytes",
+        |Value::Nat(n), Value::Bytes(b)| {
+            Ok(Value::Bytes(if *n as usize >= b.len() {
+                Vector::new()
+            } else {
+                b.skip(*n as usize)
+            }))
+        }
+    );
+    builtin!(
+        registry,
+        "Bytes.at",
+        "Nat -> Bytes -> Optional Nat",
+        refs => |Value::Nat(n), Value::Bytes(b)| {
+            Ok(match b.get(*n as usize) {
+                Some(byte) => refs.some(Arc::new(Value::Nat(*byte as u64))),
+                None => refs.none(),
+            })
+        }
+    );
+    // Lots of `++`, `take` and `drop` can leave the tree unbalanced; this gives you a fresh one.
+    builtin!(
+        registry,
+        "Bytes.flatten",
+        "Bytes -> Bytes",
+        |Value::Bytes(b)| Ok(Value::Bytes(b.iter().cloned().collect()))
+    );
+
+    base_codec!(
+        registry,
+        "Bytes.toBase16",
+        "Bytes.fromBase16",
+        to_base16,
+        from_base16
+    );
+    base_codec!(
+        registry,
+        "Bytes.toBase32",
+        "Bytes.fromBase32",
+        to_base32,
+        from_base32
+    );
+    base_codec!(
+        registry,
+        "Bytes.toBase64",
+        "Bytes.fromBase64",
+        |b: &Vec<u8>| base64::encode(b).into_bytes(),
+        |b: &Vec<u8>| base64::decode(b).ok()
+    );
+    base_codec!(
+        registry,
+        "Bytes.toBase64UrlUnpadded",
+        "Bytes.fromBase64UrlUnpadded",
+        |b: &Vec<u8>| base64::encode_config(b, base64::URL_SAFE_NO_PAD).into_bytes(),
+        |b: &Vec<u8>| base64::decode_config(b, base64::URL_SAFE_NO_PAD).ok()
+    );
+
+    nat_codec!(
+        registry,
+        "Bytes.encodeNat64be",
+        "Bytes.decodeNat64be",
+        8,
+        true
+    );
+    nat_codec!(
+        registry,
+        "Bytes.encodeNat64le",
+        "Bytes.decodeNat64le",
+        8,
+        false
+    );
+    nat_codec!(
+        registry,
+        "Bytes.encodeNat32be",
+        "Bytes.decodeNat32be",
+        4,
+        true
+    );
+    nat_codec!(
+        registry,
+        "Bytes.encodeNat32le",
+        "Bytes.decodeNat32le",
+        4,
+        false
+    );
+    nat_codec!(
+        registry,
+        "Bytes.encodeNat16be",
+        "Bytes.decodeNat16be",
+        2,
+        true
+    );
+    nat_codec!(
+        registry,
+        "Bytes.encodeNat16le",
+        "Bytes.decodeNat16le",
+        2,
+        false
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::RuntimeError;
+    use crate::types::*;
+
+    fn text(value: &str) -> Arc<Value> {
+        Arc::new(Value::Bytes(value.bytes().collect()))
+    }
+
+    fn env_with_either() -> (RuntimeEnv, Reference) {
+        let mut env = RuntimeEnv::new();
+        let id = Id::from_string("either");
+        let constructor = |name: &str| {
+            (
+                Symbol {
+                    num: 0,
+                    text: name.to_owned(),
+                    unique: 0,
+                },
+                ABT::Tm(Type::Ref(Reference::DerivedId(id.clone()))),
+            )
+        };
+        env.types.insert(
+            id.clone(),
+            TypeDecl::Data(DataDecl {
+                modifier: Modifier::Structural,
+                bound: vec![],
+                constructors: vec![
+                    constructor("builtin.Either.Left"),
+                    constructor("builtin.Either.Right"),
+                ],
+            }),
+        );
+        env.find_either();
+        (env, Reference::DerivedId(id))
+    }
+
+    #[test]
+    fn decoders_return_either() {
+        let (env, either) = env_with_either();
+        match env.builtins.call("Bytes.fromBase16", vec![text("666f6f")]) {
+            Ok(Value::PartialConstructor(reference, 1, args)) => {
+                assert_eq!(reference, either);
+                assert_eq!(args, im::Vector::from(vec![text("foo")]));
+            }
+            other => panic!("Expected a Right, got {:?}", other),
+        }
+        match env.builtins.call("Bytes.fromBase32", vec![text("MZXW6==")]) {
+            Ok(Value::PartialConstructor(reference, 0, args)) => {
+                assert_eq!(reference, either);
+                assert!(matches!(&*args[0], Value::Text(_)));
+            }
+            other => panic!("Expected a Left, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn decoders_need_either() {
+        let env = RuntimeEnv::new();
+        match env.builtins.call("Bytes.fromBase64", vec![text("Zm9v")]) {
+            Err(RuntimeError::MissingType(name)) => assert_eq!(name, "Either"),
+            other => panic!("Expected a MissingType error, got {:?}", other),
+        }
+    }
+}
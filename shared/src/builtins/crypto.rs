@@ -0,0 +1,161 @@
+use super::BuiltinRegistry;
+use crate::error::RuntimeError;
+use crate::types::{Reference, Value};
+use blake2::digest::consts::U32;
+use blake2::{Blake2b, Blake2b512, Blake2s256};
+use hmac::{Mac, SimpleHmac};
+use sha2::digest::{core_api::BlockSizeUser, Digest};
+use sha2::{Sha256, Sha512};
+use sha3::{Sha3_256, Sha3_512};
+
+type HashFn = fn(&[u8]) -> Vec<u8>;
+type HmacFn = fn(&[u8], &[u8]) -> Vec<u8>;
+
+fn hash_with<D: Digest>(data: &[u8]) -> Vec<u8> {
+    D::digest(data).to_vec()
+}
+
+// `SimpleHmac`, because the blake2 hashes don't support the faster `Hmac`
+fn hmac_with<D: Digest + BlockSizeUser>(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = <SimpleHmac<D> as Mac>::new_from_slice(key).expect("HMAC takes keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+// `crypto.HashAlgorithm` values are references to the builtins that name them.
+const ALGORITHMS: &[(&str, HashFn, HmacFn)] = &[
+    (
+        "crypto.HashAlgorithm.Sha3_512",
+        hash_with::<Sha3_512>,
+        hmac_with::<Sha3_512>,
+    ),
+    (
+        "crypto.HashAlgorithm.Sha3_256",
+        hash_with::<Sha3_256>,
+        hmac_with::<Sha3_256>,
+    ),
+    (
+        "crypto.HashAlgorithm.Sha2_512",
+        hash_with::<Sha512>,
+        hmac_with::<Sha512>,
+    ),
+    (
+        "crypto.HashAlgorithm.Sha2_256",
+        hash_with::<Sha256>,
+        hmac_with::<Sha256>,
+    ),
+    (
+        "crypto.HashAlgorithm.Blake2b_512",
+        hash_with::<Blake2b512>,
+        hmac_with::<Blake2b512>,
+    ),
+    (
+        "crypto.HashAlgorithm.Blake2b_256",
+        hash_with::<Blake2b<U32>>,
+        hmac_with::<Blake2b<U32>>,
+    ),
+    (
+        "crypto.HashAlgorithm.Blake2s_256",
+        hash_with::<Blake2s256>,
+        hmac_with::<Blake2s256>,
+    ),
+];
+
+fn algorithm(value: &Value) -> Result<(HashFn, HmacFn), RuntimeError> {
+    match value {
+        Value::Ref(Reference::Builtin(name)) => ALGORITHMS
+            .iter()
+            .find(|(alg, _, _)| alg == name)
+            .map(|(_, hash, hmac)| (*hash, *hmac)),
+        _ => None,
+    }
+    .ok_or_else(|| {
+        RuntimeError::UnexpectedValue("a HashAlgorithm".to_owned(), Box::new(value.clone()))
+    })
+}
+
+fn to_vec(bytes: &im::Vector<u8>) -> Vec<u8> {
+    bytes.iter().cloned().collect()
+}
+
+// The haskell runtime hashes its own serialization of the value, which we can't
+// reproduce, so `crypto.hash` & `crypto.hmac` of non-Bytes values won't agree with
+// it. They are stable across our native & wasm builds though, as long as the value
+// is plain data: functions refer to their code by its index in the env, which
+// depends on the order it was loaded in, so we refuse to hash those.
+fn serialize(value: &Value) -> Result<Vec<u8>, RuntimeError> {
+    if !is_data(value) {
+        return Err(RuntimeError::UnexpectedValue(
+            "a value without functions in it".to_owned(),
+            Box::new(value.clone()),
+        ));
+    }
+    bincode::serialize(value)
+        .map_err(|err| RuntimeError::Internal(format!("Unable to serialize value: {}", err)))
+}
+
+fn is_data(value: &Value) -> bool {
+    use Value::*;
+    match value {
+        Int(_) | Nat(_) | Float(_) | Boolean(_) | Text(_) | Bytes(_) | Char(_) => true,
+        Ref(_) | TermLink(_) | TypeLink(_) | Constructor(..) => true,
+        PartialConstructor(_, _, args) => args.iter().all(|arg| is_data(arg)),
+        Sequence(items) => items.iter().all(|item| is_data(item)),
+        _ => false,
+    }
+}
+
+macro_rules! algorithm {
+    ($registry:expr, $name:expr) => {
+        builtin!($registry, $name, "crypto.HashAlgorithm", || Ok(Value::Ref(
+            Reference::Builtin($name.to_owned())
+        )));
+    };
+}
+
+pub fn register(registry: &mut BuiltinRegistry) {
+    algorithm!(registry, "crypto.HashAlgorithm.Sha3_512");
+    algorithm!(registry, "crypto.HashAlgorithm.Sha3_256");
+    algorithm!(registry, "crypto.HashAlgorithm.Sha2_512");
+    algorithm!(registry, "crypto.HashAlgorithm.Sha2_256");
+    algorithm!(registry, "crypto.HashAlgorithm.Blake2b_512");
+    algorithm!(registry, "crypto.HashAlgorithm.Blake2b_256");
+    algorithm!(registry, "crypto.HashAlgorithm.Blake2s_256");
+
+    builtin!(
+        registry,
+        "crypto.hashBytes",
+        "crypto.HashAlgorithm -> Bytes -> Bytes",
+        |alg, Value::Bytes(data)| {
+            let (hash, _) = algorithm(alg)?;
+            Ok(Value::Bytes(hash(&to_vec(data)).into()))
+        }
+    );
+    builtin!(
+        registry,
+        "crypto.hash",
+        "crypto.HashAlgorithm -> a -> Bytes",
+        |alg, value| {
+            let (hash, _) = algorithm(alg)?;
+            Ok(Value::Bytes(hash(&serialize(value)?).into()))
+        }
+    );
+    builtin!(
+        registry,
+        "crypto.hmacBytes",
+        "crypto.HashAlgorithm -> Bytes -> Bytes -> Bytes",
+        |alg, Value::Bytes(key), Value::Bytes(data)| {
+            let (_, hmac) = algorithm(alg)?;
+            Ok(Value::Bytes(hmac(&to_vec(key), &to_vec(data)).into()))
+        }
+    );
+    builtin!(
+        registry,
+        "crypto.hmac",
+        "crypto.HashAlgorithm -> Bytes -> a -> Bytes",
+        |alg, Value::Bytes(key), value| {
+            let (_, hmac) = algorithm(alg)?;
+            Ok(Value::Bytes(hmac(&to_vec(key), &serialize(value)?).into()))
+        }
+    );
+}
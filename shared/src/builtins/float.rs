@@ -0,0 +1,252 @@
+use super::BuiltinRegistry;
+use crate::types::Value;
+use std::sync::Arc;
+
+macro_rules! unary {
+    ($registry:expr, $name:expr, $f:expr) => {
+        builtin!($registry, $name, "Float -> Float", |Value::Float(a)| Ok(
+            Value::Float($f(*a))
+        ))
+    };
+}
+
+macro_rules! binary {
+    ($registry:expr, $name:expr, $f:expr) => {
+        builtin!(
+            $registry,
+            $name,
+            "Float -> Float -> Float",
+            |Value::Float(a), Value::Float(b)| Ok(Value::Float($f(*a, *b)))
+        )
+    };
+}
+
+macro_rules! to_int {
+    ($registry:expr, $name:expr, $f:expr) => {
+        builtin!($registry, $name, "Float -> Int", |Value::Float(a)| Ok(
+            Value::Int(to_int($f(*a)))
+        ))
+    };
+}
+
+// GHC converts with `double2Int`, which gives minBound for NaN, infinity
+// and anything else that's out of range. Rust's `as` would saturate instead.
+fn to_int(f: f64) -> i64 {
+    if f.is_finite() && f >= -9223372036854775808.0 && f < 9223372036854775808.0 {
+        f as i64
+    } else {
+        std::i64::MIN
+    }
+}
+
+// Haskell's `round` rounds halves to the nearest even number
+fn round_half_even(f: f64) -> f64 {
+    let rounded = f.round();
+    if (rounded - f).abs() == 0.5 && rounded % 2.0 != 0.0 {
+        rounded - f.signum()
+    } else {
+        rounded
+    }
+}
+
+// These are the defaults from haskell's `Ord`, which behave differently
+// from rust's `f64::max` & `min` when NaN is involved.
+fn max(a: f64, b: f64) -> f64 {
+    if a <= b {
+        b
+    } else {
+        a
+    }
+}
+
+fn min(a: f64, b: f64) -> f64 {
+    if a <= b {
+        a
+    } else {
+        b
+    }
+}
+
+pub fn register(registry: &mut BuiltinRegistry) {
+    builtin!(
+        registry,
+        "Float.+",
+        "Float -> Float -> Float",
+        |Value::Float(a), Value::Float(b)| { Ok(Value::Float(a + b)) }
+    );
+    builtin!(
+        registry,
+        "Float.-",
+        "Float -> Float -> Float",
+        |Value::Float(a), Value::Float(b)| { Ok(Value::Float(a - b)) }
+    );
+    builtin!(
+        registry,
+        "Float.*",
+        "Float -> Float -> Float",
+        |Value::Float(a), Value::Float(b)| { Ok(Value::Float(a * b)) }
+    );
+    builtin!(
+        registry,
+        "Float./",
+        "Float -> Float -> Float",
+        |Value::Float(a), Value::Float(b)| { Ok(Value::Float(a / b)) }
+    );
+    builtin!(
+        registry,
+        "Float.<",
+        "Float -> Float -> Boolean",
+        |Value::Float(a), Value::Float(b)| { Ok(Value::Boolean(a < b)) }
+    );
+    builtin!(
+        registry,
+        "Float.<=",
+        "Float -> Float -> Boolean",
+        |Value::Float(a), Value::Float(b)| { Ok(Value::Boolean(a <= b)) }
+    );
+    builtin!(
+        registry,
+        "Float.>",
+        "Float -> Float -> Boolean",
+        |Value::Float(a), Value::Float(b)| { Ok(Value::Boolean(a > b)) }
+    );
+    builtin!(
+        registry,
+        "Float.>=",
+        "Float -> Float -> Boolean",
+        |Value::Float(a), Value::Float(b)| { Ok(Value::Boolean(a >= b)) }
+    );
+    builtin!(
+        registry,
+        "Float.==",
+        "Float -> Float -> Boolean",
+        |Value::Float(a), Value::Float(b)| { Ok(Value::Boolean(a == b)) }
+    );
+
+    unary!(registry, "Float.acos", f64::acos);
+    unary!(registry, "Float.asin", f64::asin);
+    unary!(registry, "Float.atan", f64::atan);
+    binary!(registry, "Float.atan2", f64::atan2);
+    unary!(registry, "Float.cos", f64::cos);
+    unary!(registry, "Float.sin", f64::sin);
+    unary!(registry, "Float.tan", f64::tan);
+
+    unary!(registry, "Float.acosh", f64::acosh);
+    unary!(registry, "Float.asinh", f64::asinh);
+    unary!(registry, "Float.atanh", f64::atanh);
+    unary!(registry, "Float.cosh", f64::cosh);
+    unary!(registry, "Float.sinh", f64::sinh);
+    unary!(registry, "Float.tanh", f64::tanh);
+
+    unary!(registry, "Float.exp", f64::exp);
+    unary!(registry, "Float.log", f64::ln);
+    // The base comes first
+    binary!(registry, "Float.logBase", |base: f64, x: f64| x.ln()
+        / base.ln());
+    binary!(registry, "Float.pow", f64::powf);
+    unary!(registry, "Float.sqrt", f64::sqrt);
+
+    to_int!(registry, "Float.ceiling", f64::ceil);
+    to_int!(registry, "Float.floor", f64::floor);
+    to_int!(registry, "Float.round", round_half_even);
+    to_int!(registry, "Float.truncate", f64::trunc);
+
+    unary!(registry, "Float.abs", f64::abs);
+    binary!(registry, "Float.max", max);
+    binary!(registry, "Float.min", min);
+
+    builtin!(registry, "Float.toText", "Float -> Text", |Value::Float(
+        f,
+    )| {
+        Ok(Value::Text(show(*f)))
+    });
+    builtin!(registry, "Float.fromText", "Text -> Optional Float", refs => |Value::Text(t)| {
+        Ok(match read(t.trim()) {
+            Some(f) => refs.some(Arc::new(Value::Float(f))),
+            None => refs.none(),
+        })
+    });
+}
+
+// Format a float the way haskell's `show` does, which is what ucm uses.
+// That's the shortest digits that uniquely identify the number, in fixed
+// notation when 0.1 <= |f| < 10^7, and scientific notation otherwise.
+pub fn show(f: f64) -> String {
+    if f.is_nan() {
+        return "NaN".to_owned();
+    }
+    if f.is_infinite() {
+        return if f > 0.0 { "Infinity" } else { "-Infinity" }.to_owned();
+    }
+    if f.is_sign_negative() {
+        return format!("-{}", show(-f));
+    }
+    if f == 0.0 {
+        return "0.0".to_owned();
+    }
+    // rust's `{:e}` gives us the shortest digits too, as `d.ddde-x`
+    let formatted = format!("{:e}", f);
+    let (mantissa, exponent) = match formatted.find('e') {
+        Some(idx) => (&formatted[..idx], &formatted[idx + 1..]),
+        None => (formatted.as_str(), "0"),
+    };
+    let digits: String = mantissa.chars().filter(|c| *c != '.').collect();
+    // so that f = 0.ddd * 10^exponent
+    let exponent = exponent.parse::<i32>().unwrap_or(0) + 1;
+    if exponent < 0 || exponent > 7 {
+        let (first, rest) = digits.split_at(1);
+        format!(
+            "{}.{}e{}",
+            first,
+            if rest.is_empty() { "0" } else { rest },
+            exponent - 1
+        )
+    } else if exponent == 0 {
+        format!("0.{}", digits)
+    } else {
+        let exponent = exponent as usize;
+        if digits.len() > exponent {
+            format!("{}.{}", &digits[..exponent], &digits[exponent..])
+        } else {
+            format!("{}{}.0", digits, "0".repeat(exponent - digits.len()))
+        }
+    }
+}
+
+// The inverse of `show`. This is stricter than rust's `parse`, which accepts
+// things like ".5" and "inf".
+pub fn read(text: &str) -> Option<f64> {
+    match text {
+        "NaN" => return Some(std::f64::NAN),
+        "Infinity" => return Some(std::f64::INFINITY),
+        "-Infinity" => return Some(std::f64::NEG_INFINITY),
+        _ => (),
+    }
+    let unsigned = if text.starts_with('-') {
+        &text[1..]
+    } else {
+        text
+    };
+    let (number, exponent) = match unsigned.find(|c| c == 'e' || c == 'E') {
+        Some(idx) => (&unsigned[..idx], Some(&unsigned[idx + 1..])),
+        None => (unsigned, None),
+    };
+    let is_digits = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+    let number_ok = match number.find('.') {
+        Some(idx) => is_digits(&number[..idx]) && is_digits(&number[idx + 1..]),
+        None => is_digits(number),
+    };
+    let exponent_ok = match exponent {
+        None => true,
+        Some(exponent) => is_digits(if exponent.starts_with('-') || exponent.starts_with('+') {
+            &exponent[1..]
+        } else {
+            exponent
+        }),
+    };
+    if number_ok && exponent_ok {
+        text.parse().ok()
+    } else {
+        None
+    }
+}
@@ -0,0 +1,206 @@
+// Ints are 64 bit, and wrap on overflow like they do in the haskell runtime.
+// Dividing by zero aborts.
+use super::BuiltinRegistry;
+use crate::error::RuntimeError;
+use crate::types::Value;
+use std::sync::Arc;
+
+// Haskell's `div` rounds towards negative infinity, unlike rust's `/`
+fn div(a: i64, b: i64) -> Result<i64, RuntimeError> {
+    if b == 0 {
+        return Err(RuntimeError::DivideByZero);
+    }
+    let q = a.wrapping_div(b);
+    if a.wrapping_rem(b) != 0 && ((a < 0) != (b < 0)) {
+        Ok(q - 1)
+    } else {
+        Ok(q)
+    }
+}
+
+// Haskell's `mod` takes the sign of the divisor, unlike rust's `%`
+fn modulo(a: i64, b: i64) -> Result<i64, RuntimeError> {
+    if b == 0 {
+        return Err(RuntimeError::DivideByZero);
+    }
+    let r = a.wrapping_rem(b);
+    if r != 0 && ((r < 0) != (b < 0)) {
+        Ok(r + b)
+    } else {
+        Ok(r)
+    }
+}
+
+fn shift_right(a: i64, b: u64) -> i64 {
+    if b >= 64 {
+        if a < 0 {
+            -1
+        } else {
+            0
+        }
+    } else {
+        a >> b
+    }
+}
+
+pub fn register(registry: &mut BuiltinRegistry) {
+    builtin!(
+        registry,
+        "Int.+",
+        "Int -> Int -> Int",
+        |Value::Int(a), Value::Int(b)| { Ok(Value::Int(a.wrapping_add(*b))) }
+    );
+    builtin!(
+        registry,
+        "Int.-",
+        "Int -> Int -> Int",
+        |Value::Int(a), Value::Int(b)| { Ok(Value::Int(a.wrapping_sub(*b))) }
+    );
+    builtin!(
+        registry,
+        "Int.*",
+        "Int -> Int -> Int",
+        |Value::Int(a), Value::Int(b)| { Ok(Value::Int(a.wrapping_mul(*b))) }
+    );
+    builtin!(
+        registry,
+        "Int./",
+        "Int -> Int -> Int",
+        |Value::Int(a), Value::Int(b)| { Ok(Value::Int(div(*a, *b)?)) }
+    );
+    builtin!(
+        registry,
+        "Int.mod",
+        "Int -> Int -> Int",
+        |Value::Int(a), Value::Int(b)| { Ok(Value::Int(modulo(*a, *b)?)) }
+    );
+    builtin!(registry, "Int.<", "Int -> Int -> Boolean", |Value::Int(
+        a,
+    ),
+                                                          Value::Int(
+        b,
+    )| {
+        Ok(Value::Boolean(a < b))
+    });
+    builtin!(registry, "Int.<=", "Int -> Int -> Boolean", |Value::Int(
+        a,
+    ),
+                                                           Value::Int(
+        b,
+    )| {
+        Ok(Value::Boolean(a <= b))
+    });
+    builtin!(registry, "Int.>", "Int -> Int -> Boolean", |Value::Int(
+        a,
+    ),
+                                                          Value::Int(
+        b,
+    )| {
+        Ok(Value::Boolean(a > b))
+    });
+    builtin!(registry, "Int.>=", "Int -> Int -> Boolean", |Value::Int(
+        a,
+    ),
+                                                           Value::Int(
+        b,
+    )| {
+        Ok(Value::Boolean(a >= b))
+    });
+    builtin!(registry, "Int.==", "Int -> Int -> Boolean", |Value::Int(
+        a,
+    ),
+                                                           Value::Int(
+        b,
+    )| {
+        Ok(Value::Boolean(a == b))
+    });
+    builtin!(
+        registry,
+        "Int.and",
+        "Int -> Int -> Int",
+        |Value::Int(a), Value::Int(b)| { Ok(Value::Int(a & b)) }
+    );
+    builtin!(
+        registry,
+        "Int.or",
+        "Int -> Int -> Int",
+        |Value::Int(a), Value::Int(b)| { Ok(Value::Int(a | b)) }
+    );
+    builtin!(
+        registry,
+        "Int.xor",
+        "Int -> Int -> Int",
+        |Value::Int(a), Value::Int(b)| { Ok(Value::Int(a ^ b)) }
+    );
+    // Multiplication wraps the same way for signed & unsigned numbers
+    builtin!(
+        registry,
+        "Int.pow",
+        "Int -> Nat -> Int",
+        |Value::Int(a), Value::Nat(b)| { Ok(Value::Int(super::nat::pow(*a as u64, *b) as i64)) }
+    );
+    builtin!(
+        registry,
+        "Int.shiftLeft",
+        "Int -> Nat -> Int",
+        |Value::Int(a), Value::Nat(b)| { Ok(Value::Int(if *b >= 64 { 0 } else { a << b })) }
+    );
+    builtin!(
+        registry,
+        "Int.shiftRight",
+        "Int -> Nat -> Int",
+        |Value::Int(a), Value::Nat(b)| { Ok(Value::Int(shift_right(*a, *b))) }
+    );
+
+    builtin!(registry, "Int.increment", "Int -> Int", |Value::Int(i)| Ok(
+        Value::Int(i.wrapping_add(1))
+    ));
+    builtin!(registry, "Int.negate", "Int -> Int", |Value::Int(i)| Ok(
+        Value::Int(i.wrapping_neg())
+    ));
+    builtin!(registry, "Int.isEven", "Int -> Boolean", |Value::Int(i)| {
+        Ok(Value::Boolean(i % 2 == 0))
+    });
+    builtin!(registry, "Int.isOdd", "Int -> Boolean", |Value::Int(i)| {
+        Ok(Value::Boolean(i % 2 != 0))
+    });
+    builtin!(registry, "Int.signum", "Int -> Int", |Value::Int(i)| Ok(
+        Value::Int(i.signum())
+    ));
+    // Negative numbers become 0
+    builtin!(registry, "Int.truncate0", "Int -> Nat", |Value::Int(i)| Ok(
+        Value::Nat(if *i < 0 { 0 } else { *i as u64 })
+    ));
+    builtin!(registry, "Int.leadingZeros", "Int -> Nat", |Value::Int(
+        i,
+    )| Ok(
+        Value::Nat(i.leading_zeros() as u64)
+    ));
+    builtin!(registry, "Int.trailingZeros", "Int -> Nat", |Value::Int(
+        i,
+    )| Ok(
+        Value::Nat(i.trailing_zeros() as u64)
+    ));
+    builtin!(registry, "Int.popCount", "Int -> Nat", |Value::Int(i)| Ok(
+        Value::Nat(i.count_ones() as u64)
+    ));
+    builtin!(registry, "Int.toText", "Int -> Text", |Value::Int(i)| {
+        Ok(Value::Text(i.to_string()))
+    });
+    builtin!(
+        registry,
+        "Int.complement",
+        "Int -> Int",
+        |Value::Int(i)| Ok(Value::Int(!i))
+    );
+    builtin!(registry, "Int.fromText", "Text -> Optional Int", refs => |Value::Text(t)| {
+        let t = t.trim();
+        Ok(match t.parse() {
+            Ok(i) => refs.some(Arc::new(Value::Int(i))),
+            Err(_) => refs.none(),
+        })
+    });
+    builtin!(registry, "Int.toFloat", "Int -> Float", |Value::Int(i)| Ok(
+        Value::Float(*i as f64)
+    ));
+}
@@ -0,0 +1,156 @@
+use super::{invalid_args, Builtin, BuiltinRegistry};
+use crate::types::Value;
+use im::Vector;
+use std::sync::Arc;
+
+pub fn register(registry: &mut BuiltinRegistry) {
+    builtin!(registry, "List.empty", "[a]", || Ok(Value::Sequence(
+        Vector::new()
+    )));
+    builtin!(registry, "List.size", "[a] -> Nat", |Value::Sequence(s)| {
+        Ok(Value::Nat(s.len() as u64))
+    });
+    builtin!(registry, "List.at", "Nat -> [a] -> Optional a", refs => |Value::Nat(a), Value::Sequence(l)| {
+        Ok(if *a < l.len() as u64 {
+            refs.some(l[*a as usize].clone())
+        } else {
+            refs.none()
+        })
+    });
+    // These don't use `builtin!`, so that the element can be added without copying it
+    registry.add(Builtin {
+        name: "List.cons",
+        arity: 2,
+        signature: "a -> [a] -> [a]",
+        run: |_, args| match &*args[1] {
+            Value::Sequence(l) => {
+                let mut l = l.clone();
+                l.push_front(args[0].clone());
+                Ok(Value::Sequence(l))
+            }
+            _ => Err(invalid_args("List.cons", args)),
+        },
+    });
+    registry.add(Builtin {
+        name: "List.snoc",
+        arity: 2,
+        signature: "[a] -> a -> [a]",
+        run: |_, args| match &*args[0] {
+            Value::Sequence(l) => {
+                let mut l = l.clone();
+                l.push_back(args[1].clone());
+                Ok(Value::Sequence(l))
+            }
+            _ => Err(invalid_args("List.snoc", args)),
+        },
+    });
+    registry.alias("List.cons", "List.+:");
+    registry.alias("List.snoc", "List.:+");
+    builtin!(
+        registry,
+        "List.take",
+        "Nat -> [a] -> [a]",
+        |Value::Nat(n), Value::Sequence(l)| {
+            Ok(Value::Sequence(l.take((*n as usize).min(l.len()))))
+        }
+    );
+    builtin!(
+        registry,
+        "List.drop",
+        "Nat -> [a] -> [a]",
+        |Value::Nat(n), Value::Sequence(l)| {
+            Ok(Value::Sequence(if *n as usize >= l.len() {
+                Vector::new()
+            } else {
+                l.skip(*n as usize)
+            }))
+        }
+    );
+    builtin!(
+        registry,
+        "List.++",
+        "[a] -> [a] -> [a]",
+        |Value::Sequence(l0), Value::Sequence(l1)| {
+            let mut l = l0.clone();
+            l.append(l1.clone());
+            Ok(Value::Sequence(l))
+        }
+    );
+    // The items from `start` up to (but not including) `end`, clamped to the list
+    builtin!(
+        registry,
+        "List.slice",
+        "Nat -> Nat -> [a] -> [a]",
+        |Value::Nat(start), Value::Nat(end), Value::Sequence(l)| {
+            let end = (*end as usize).min(l.len());
+            let start = (*start as usize).min(end);
+            Ok(Value::Sequence(l.clone().slice(start..end)))
+        }
+    );
+    builtin!(registry, "List.reverse", "[a] -> [a]", |Value::Sequence(
+        l,
+    )| {
+        Ok(Value::Sequence(l.iter().rev().cloned().collect()))
+    });
+    // Like cons & snoc, these keep the item's Arc.
+    // Out of range indices leave the list as it was.
+    registry.add(Builtin {
+        name: "List.replace",
+        arity: 3,
+        signature: "Nat -> a -> [a] -> [a]",
+        run: |_, args| match (&*args[0], &*args[2]) {
+            (Value::Nat(n), Value::Sequence(l)) => {
+                Ok(Value::Sequence(if (*n as usize) < l.len() {
+                    l.update(*n as usize, args[1].clone())
+                } else {
+                    l.clone()
+                }))
+            }
+            _ => Err(invalid_args("List.replace", args)),
+        },
+    });
+    // Indices past the end append the item
+    registry.add(Builtin {
+        name: "List.insert",
+        arity: 3,
+        signature: "Nat -> a -> [a] -> [a]",
+        run: |_, args| match (&*args[0], &*args[2]) {
+            (Value::Nat(n), Value::Sequence(l)) => {
+                let mut l = l.clone();
+                l.insert((*n as usize).min(l.len()), args[1].clone());
+                Ok(Value::Sequence(l))
+            }
+            _ => Err(invalid_args("List.insert", args)),
+        },
+    });
+    builtin!(
+        registry,
+        "List.uncons",
+        "[a] -> Optional (a, [a])",
+        refs => |Value::Sequence(l)| {
+            Ok(if l.is_empty() {
+                refs.none()
+            } else {
+                refs.some(Arc::new(refs.tuple(vec![
+                    l[0].clone(),
+                    Arc::new(Value::Sequence(l.skip(1))),
+                ])))
+            })
+        }
+    );
+    builtin!(
+        registry,
+        "List.unsnoc",
+        "[a] -> Optional ([a], a)",
+        refs => |Value::Sequence(l)| {
+            Ok(if l.is_empty() {
+                refs.none()
+            } else {
+                refs.some(Arc::new(refs.tuple(vec![
+                    Arc::new(Value::Sequence(l.take(l.len() - 1))),
+                    l[l.len() - 1].clone(),
+                ])))
+            })
+        }
+    );
+}
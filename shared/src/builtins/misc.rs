@@ -0,0 +1,18 @@
+use super::BuiltinRegistry;
+use crate::error::RuntimeError;
+use crate::types::Value;
+
+pub fn register(registry: &mut BuiltinRegistry) {
+    builtin!(
+        registry,
+        "Boolean.not",
+        "Boolean -> Boolean",
+        |Value::Boolean(b)| { Ok(Value::Boolean(!b)) }
+    );
+    builtin!(registry, "bug", "a -> b", |value| Err(RuntimeError::Bug(
+        Box::new((*value).clone())
+    )));
+    builtin!(registry, "todo", "a -> b", |value| Err(RuntimeError::Todo(
+        Box::new((*value).clone())
+    )));
+}
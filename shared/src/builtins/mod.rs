@@ -0,0 +1,218 @@
+// The native implementations of unison's builtins, keyed by name.
+// Each family of builtins lives in its own module, and adds itself to the
+// registry in its `register` function.
+use super::error::RuntimeError;
+use super::types::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+macro_rules! count {
+    () => (0usize);
+    ($head:tt $($tail:tt)*) => (1usize + count!($($tail)*));
+}
+
+// Register a builtin by pattern matching on its arguments. The arity is the number
+// of patterns; arguments that don't match result in `InvalidBuiltinArgs`.
+//
+//   builtin!(registry, "Nat.+", "Nat -> Nat -> Nat", |Value::Nat(a), Value::Nat(b)| {
+//       Ok(Value::Nat(a + b))
+//   });
+//
+// Prefix the patterns with `refs =>` if the body needs the `Refs`.
+// Builtins with no arguments are constants, which get inlined when translating to IR.
+macro_rules! builtin {
+    ($registry:expr, $name:expr, $signature:expr, || $body:expr) => {
+        builtin!($registry, $name, $signature, _refs => | | $body)
+    };
+    ($registry:expr, $name:expr, $signature:expr, |$($arg:pat),*| $body:expr) => {
+        builtin!($registry, $name, $signature, _refs => |$($arg),*| $body)
+    };
+    ($registry:expr, $name:expr, $signature:expr, $refs:ident => |$($arg:pat),*| $body:expr) => {
+        $registry.add($crate::builtins::Builtin {
+            name: $name,
+            arity: count!($($arg)*),
+            signature: $signature,
+            run: |$refs, args| match args.iter().map(|a| &**a).collect::<Vec<&$crate::types::Value>>().as_slice() {
+                [$($arg),*] => $body,
+                _ => Err($crate::builtins::invalid_args($name, args)),
+            },
+        })
+    };
+}
+
+mod bytes;
+mod crypto;
+mod float;
+mod int;
+mod list;
+mod misc;
+mod nat;
+mod text;
+pub mod universal;
+pub mod v1;
+
+// References to the types that builtins need to construct values of.
+#[derive(Debug, Clone)]
+pub struct Refs {
+    pub option: Reference,
+    pub tuple: Reference,
+    pub unit: Reference,
+    // `Either` doesn't have a hash we know ahead of time, so it's found among the
+    // program's types (see `RuntimeEnv::find_either`).
+    pub either: Option<Reference>,
+}
+
+impl Refs {
+    pub fn some(&self, value: Arc<Value>) -> Value {
+        Value::PartialConstructor(self.option.clone(), 1, im::Vector::from(vec![value]))
+    }
+
+    pub fn none(&self) -> Value {
+        Value::Constructor(self.option.clone(), 0)
+    }
+
+    pub fn left(&self, value: Arc<Value>) -> Result<Value, RuntimeError> {
+        self.either_of(0, value)
+    }
+
+    pub fn right(&self, value: Arc<Value>) -> Result<Value, RuntimeError> {
+        self.either_of(1, value)
+    }
+
+    fn either_of(&self, number: usize, value: Arc<Value>) -> Result<Value, RuntimeError> {
+        match &self.either {
+            Some(either) => Ok(Value::PartialConstructor(
+                either.clone(),
+                number,
+                im::Vector::from(vec![value]),
+            )),
+            None => Err(RuntimeError::MissingType("Either".to_owned())),
+        }
+    }
+
+    // Tuples are nested `Cons`es, ending in unit
+    pub fn tuple(&self, items: Vec<Arc<Value>>) -> Value {
+        items
+            .into_iter()
+            .rev()
+            .fold(Value::Constructor(self.unit.clone(), 0), |rest, item| {
+                Value::PartialConstructor(
+                    self.tuple.clone(),
+                    0,
+                    im::Vector::from(vec![item, Arc::new(rest)]),
+                )
+            })
+    }
+}
+
+// The arguments are guaranteed to number `arity`.
+pub type BuiltinFn = fn(&Refs, &[Arc<Value>]) -> Result<Value, RuntimeError>;
+
+#[derive(Clone)]
+pub struct Builtin {
+    pub name: &'static str,
+    pub arity: usize,
+    // The unison type, e.g. "Nat -> [a] -> Optional a"
+    pub signature: &'static str,
+    pub run: BuiltinFn,
+}
+
+impl std::fmt::Debug for Builtin {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "Builtin<{} : {}>", self.name, self.signature)
+    }
+}
+
+pub fn invalid_args(name: &str, args: &[Arc<Value>]) -> RuntimeError {
+    RuntimeError::InvalidBuiltinArgs(
+        name.to_owned(),
+        args.iter().map(|arg| (**arg).clone()).collect(),
+    )
+}
+
+#[derive(Debug, Clone)]
+pub struct BuiltinRegistry {
+    builtins: HashMap<&'static str, Builtin>,
+    pub refs: Refs,
+}
+
+impl Default for BuiltinRegistry {
+    // All of the builtins that we support
+    fn default() -> Self {
+        let mut registry = BuiltinRegistry::empty();
+        bytes::register(&mut registry);
+        crypto::register(&mut registry);
+        float::register(&mut registry);
+        int::register(&mut registry);
+        list::register(&mut registry);
+        misc::register(&mut registry);
+        nat::register(&mut registry);
+        text::register(&mut registry);
+        universal::register(&mut registry);
+        registry
+    }
+}
+
+impl BuiltinRegistry {
+    pub fn empty() -> Self {
+        BuiltinRegistry {
+            builtins: HashMap::new(),
+            refs: Refs {
+                option: Reference::from_hash(crate::convert::OPTION_HASH),
+                tuple: Reference::from_hash(crate::convert::TUPLE_HASH),
+                unit: Reference::from_hash(crate::convert::UNIT_HASH),
+                either: None,
+            },
+        }
+    }
+
+    // Replaces any existing builtin with the same name, so hosts
+    // can override the default implementations too.
+    pub fn add(&mut self, builtin: Builtin) {
+        self.builtins.insert(builtin.name, builtin);
+    }
+
+    // Register an existing builtin under another name
+    pub fn alias(&mut self, name: &'static str, alias: &'static str) {
+        if let Some(builtin) = self.builtins.get(name) {
+            let builtin = Builtin {
+                name: alias,
+                ..builtin.clone()
+            };
+            self.add(builtin);
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Builtin> {
+        self.builtins.get(name)
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        let mut names: Vec<&'static str> = self.builtins.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    // The builtins of unison v1 that we don't have an implementation for.
+    pub fn unsupported(&self) -> Vec<&'static str> {
+        v1::BUILTINS
+            .iter()
+            .filter(|name| !self.builtins.contains_key(*name))
+            .cloned()
+            .collect()
+    }
+
+    // Apply a builtin to some arguments. If there aren't enough yet, you get
+    // a `PartialNativeApp` that's waiting for the rest.
+    pub fn call(&self, name: &str, args: Vec<Arc<Value>>) -> Result<Value, RuntimeError> {
+        let builtin = match self.get(name) {
+            Some(builtin) => builtin,
+            None => return Err(RuntimeError::UnknownBuiltin(name.to_owned())),
+        };
+        if args.len() < builtin.arity {
+            Ok(Value::PartialNativeApp(name.to_owned(), args))
+        } else {
+            (builtin.run)(&self.refs, &args)
+        }
+    }
+}
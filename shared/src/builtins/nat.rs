@@ -0,0 +1,197 @@
+// Nats are unsigned 64 bit, and wrap on overflow like they do in the haskell runtime.
+// Dividing by zero aborts.
+use super::BuiltinRegistry;
+use crate::error::RuntimeError;
+use crate::types::Value;
+use std::sync::Arc;
+
+// Wrapping exponentiation by squaring. The exponent can be any Nat,
+// so `u64::wrapping_pow` (which takes a u32) won't do.
+pub fn pow(mut base: u64, mut exponent: u64) -> u64 {
+    let mut result: u64 = 1;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = result.wrapping_mul(base);
+        }
+        base = base.wrapping_mul(base);
+        exponent >>= 1;
+    }
+    result
+}
+
+pub fn register(registry: &mut BuiltinRegistry) {
+    builtin!(
+        registry,
+        "Nat.+",
+        "Nat -> Nat -> Nat",
+        |Value::Nat(a), Value::Nat(b)| { Ok(Value::Nat(a.wrapping_add(*b))) }
+    );
+    builtin!(
+        registry,
+        "Nat.sub",
+        "Nat -> Nat -> Int",
+        |Value::Nat(a), Value::Nat(b)| { Ok(Value::Int((*a as i64).wrapping_sub(*b as i64))) }
+    );
+    builtin!(
+        registry,
+        "Nat.*",
+        "Nat -> Nat -> Nat",
+        |Value::Nat(a), Value::Nat(b)| { Ok(Value::Nat(a.wrapping_mul(*b))) }
+    );
+    builtin!(
+        registry,
+        "Nat./",
+        "Nat -> Nat -> Nat",
+        |Value::Nat(a), Value::Nat(b)| {
+            match a.checked_div(*b) {
+                Some(n) => Ok(Value::Nat(n)),
+                None => Err(RuntimeError::DivideByZero),
+            }
+        }
+    );
+    builtin!(
+        registry,
+        "Nat.mod",
+        "Nat -> Nat -> Nat",
+        |Value::Nat(a), Value::Nat(b)| {
+            match a.checked_rem(*b) {
+                Some(n) => Ok(Value::Nat(n)),
+                None => Err(RuntimeError::DivideByZero),
+            }
+        }
+    );
+    builtin!(registry, "Nat.>", "Nat -> Nat -> Boolean", |Value::Nat(
+        a,
+    ),
+                                                          Value::Nat(
+        b,
+    )| {
+        Ok(Value::Boolean(a > b))
+    });
+    builtin!(registry, "Nat.>=", "Nat -> Nat -> Boolean", |Value::Nat(
+        a,
+    ),
+                                                           Value::Nat(
+        b,
+    )| {
+        Ok(Value::Boolean(a >= b))
+    });
+    builtin!(registry, "Nat.<", "Nat -> Nat -> Boolean", |Value::Nat(
+        a,
+    ),
+                                                          Value::Nat(
+        b,
+    )| {
+        Ok(Value::Boolean(a < b))
+    });
+    builtin!(registry, "Nat.<=", "Nat -> Nat -> Boolean", |Value::Nat(
+        a,
+    ),
+                                                           Value::Nat(
+        b,
+    )| {
+        Ok(Value::Boolean(a <= b))
+    });
+    builtin!(registry, "Nat.==", "Nat -> Nat -> Boolean", |Value::Nat(
+        a,
+    ),
+                                                           Value::Nat(
+        b,
+    )| {
+        Ok(Value::Boolean(a == b))
+    });
+    builtin!(
+        registry,
+        "Nat.and",
+        "Nat -> Nat -> Nat",
+        |Value::Nat(a), Value::Nat(b)| { Ok(Value::Nat(a & b)) }
+    );
+    builtin!(
+        registry,
+        "Nat.or",
+        "Nat -> Nat -> Nat",
+        |Value::Nat(a), Value::Nat(b)| { Ok(Value::Nat(a | b)) }
+    );
+    builtin!(
+        registry,
+        "Nat.xor",
+        "Nat -> Nat -> Nat",
+        |Value::Nat(a), Value::Nat(b)| { Ok(Value::Nat(a ^ b)) }
+    );
+    builtin!(
+        registry,
+        "Nat.pow",
+        "Nat -> Nat -> Nat",
+        |Value::Nat(a), Value::Nat(b)| { Ok(Value::Nat(pow(*a, *b))) }
+    );
+    builtin!(
+        registry,
+        "Nat.shiftLeft",
+        "Nat -> Nat -> Nat",
+        |Value::Nat(a), Value::Nat(b)| { Ok(Value::Nat(if *b >= 64 { 0 } else { a << b })) }
+    );
+    builtin!(
+        registry,
+        "Nat.shiftRight",
+        "Nat -> Nat -> Nat",
+        |Value::Nat(a), Value::Nat(b)| { Ok(Value::Nat(if *b >= 64 { 0 } else { a >> b })) }
+    );
+    // Subtraction that stops at zero
+    builtin!(registry, "Nat.drop", "Nat -> Nat -> Nat", |Value::Nat(
+        a,
+    ),
+                                                         Value::Nat(
+        b,
+    )| {
+        Ok(Value::Nat(a.saturating_sub(*b)))
+    });
+
+    builtin!(registry, "Nat.increment", "Nat -> Nat", |Value::Nat(i)| Ok(
+        Value::Nat(i.wrapping_add(1))
+    ));
+    builtin!(registry, "Nat.isEven", "Nat -> Boolean", |Value::Nat(i)| {
+        Ok(Value::Boolean(i % 2 == 0))
+    });
+    builtin!(registry, "Nat.isOdd", "Nat -> Boolean", |Value::Nat(i)| {
+        Ok(Value::Boolean(i % 2 == 1))
+    });
+    builtin!(registry, "Nat.leadingZeros", "Nat -> Nat", |Value::Nat(
+        i,
+    )| Ok(
+        Value::Nat(i.leading_zeros() as u64)
+    ));
+    builtin!(registry, "Nat.trailingZeros", "Nat -> Nat", |Value::Nat(
+        i,
+    )| Ok(
+        Value::Nat(i.trailing_zeros() as u64)
+    ));
+    builtin!(registry, "Nat.popCount", "Nat -> Nat", |Value::Nat(i)| Ok(
+        Value::Nat(i.count_ones() as u64)
+    ));
+    // Nats above maxInt wrap around to negative numbers
+    builtin!(registry, "Nat.toInt", "Nat -> Int", |Value::Nat(i)| Ok(
+        Value::Int(*i as i64)
+    ));
+    builtin!(registry, "Nat.toText", "Nat -> Text", |Value::Nat(i)| {
+        Ok(Value::Text(i.to_string()))
+    });
+    builtin!(
+        registry,
+        "Nat.complement",
+        "Nat -> Nat",
+        |Value::Nat(i)| Ok(Value::Nat(!i))
+    );
+    builtin!(registry, "Nat.fromText", "Text -> Optional Nat", refs => |Value::Text(t)| {
+        Ok(match t.trim() {
+            // rust would accept a leading +
+            digits if digits.chars().all(|c| c.is_ascii_digit()) => match digits.parse() {
+                Ok(n) => refs.some(Arc::new(Value::Nat(n))),
+                Err(_) => refs.none(),
+            },
+            _ => refs.none(),
+        })
+    });
+    builtin!(registry, "Nat.toFloat", "Nat -> Float", |Value::Nat(n)| Ok(
+        Value::Float(*n as f64)
+    ));
+}
@@ -0,0 +1,198 @@
+// Text is indexed by unicode code points (rust `char`s), as it is in ucm,
+// and not by bytes.
+use super::BuiltinRegistry;
+use crate::error::RuntimeError;
+use crate::types::Value;
+use std::convert::TryFrom;
+use std::sync::Arc;
+
+fn take(text: &str, n: u64) -> &str {
+    match text.char_indices().nth(n as usize) {
+        Some((idx, _)) => &text[..idx],
+        None => text,
+    }
+}
+
+fn drop(text: &str, n: u64) -> &str {
+    match text.char_indices().nth(n as usize) {
+        Some((idx, _)) => &text[idx..],
+        None => "",
+    }
+}
+
+pub fn register(registry: &mut BuiltinRegistry) {
+    builtin!(registry, "Text.empty", "Text", || Ok(Value::Text(
+        "".to_owned()
+    )));
+    builtin!(
+        registry,
+        "Text.++",
+        "Text -> Text -> Text",
+        |Value::Text(a), Value::Text(b)| { Ok(Value::Text(a.to_owned() + b)) }
+    );
+    builtin!(
+        registry,
+        "Text.==",
+        "Text -> Text -> Boolean",
+        |Value::Text(a), Value::Text(b)| { Ok(Value::Boolean(a == b)) }
+    );
+    builtin!(
+        registry,
+        "Text.!=",
+        "Text -> Text -> Boolean",
+        |Value::Text(a), Value::Text(b)| { Ok(Value::Boolean(a != b)) }
+    );
+    // Comparing utf-8 bytes gives the same ordering as comparing code points
+    builtin!(
+        registry,
+        "Text.<=",
+        "Text -> Text -> Boolean",
+        |Value::Text(a), Value::Text(b)| { Ok(Value::Boolean(a <= b)) }
+    );
+    builtin!(
+        registry,
+        "Text.>=",
+        "Text -> Text -> Boolean",
+        |Value::Text(a), Value::Text(b)| { Ok(Value::Boolean(a >= b)) }
+    );
+    builtin!(
+        registry,
+        "Text.>",
+        "Text -> Text -> Boolean",
+        |Value::Text(a), Value::Text(b)| { Ok(Value::Boolean(a > b)) }
+    );
+    builtin!(
+        registry,
+        "Text.<",
+        "Text -> Text -> Boolean",
+        |Value::Text(a), Value::Text(b)| { Ok(Value::Boolean(a < b)) }
+    );
+    builtin!(
+        registry,
+        "Text.take",
+        "Nat -> Text -> Text",
+        |Value::Nat(n), Value::Text(t)| { Ok(Value::Text(take(t, *n).to_owned())) }
+    );
+    builtin!(
+        registry,
+        "Text.drop",
+        "Nat -> Text -> Text",
+        |Value::Nat(n), Value::Text(t)| { Ok(Value::Text(drop(t, *n).to_owned())) }
+    );
+    builtin!(registry, "Text.size", "Text -> Nat", |Value::Text(t)| {
+        Ok(Value::Nat(t.chars().count() as u64))
+    });
+    builtin!(
+        registry,
+        "Text.repeat",
+        "Nat -> Text -> Text",
+        |Value::Nat(n), Value::Text(t)| {
+            // Allocating more memory than we can get would abort the whole process
+            let too_big = || {
+                RuntimeError::UnexpectedValue(
+                    "a Text that fits in memory".to_owned(),
+                    Box::new(Value::Nat(*n)),
+                )
+            };
+            let count = usize::try_from(*n).map_err(|_| too_big())?;
+            let size = count.checked_mul(t.len()).ok_or_else(too_big)?;
+            let mut result = String::new();
+            result.try_reserve_exact(size).map_err(|_| too_big())?;
+            for _ in 0..count {
+                result.push_str(t);
+            }
+            Ok(Value::Text(result))
+        }
+    );
+    builtin!(
+        registry,
+        "Text.uncons",
+        "Text -> Optional (Char, Text)",
+        refs => |Value::Text(t)| {
+            let mut chars = t.chars();
+            Ok(match chars.next() {
+                None => refs.none(),
+                Some(c) => refs.some(Arc::new(refs.tuple(vec![
+                    Arc::new(Value::Char(c)),
+                    Arc::new(Value::Text(chars.as_str().to_owned())),
+                ]))),
+            })
+        }
+    );
+    builtin!(
+        registry,
+        "Text.unsnoc",
+        "Text -> Optional (Text, Char)",
+        refs => |Value::Text(t)| {
+            let mut chars = t.chars();
+            Ok(match chars.next_back() {
+                None => refs.none(),
+                Some(c) => refs.some(Arc::new(refs.tuple(vec![
+                    Arc::new(Value::Text(chars.as_str().to_owned())),
+                    Arc::new(Value::Char(c)),
+                ]))),
+            })
+        }
+    );
+    builtin!(
+        registry,
+        "Text.toCharList",
+        "Text -> [Char]",
+        |Value::Text(t)| {
+            Ok(Value::Sequence(
+                t.chars().map(|c| Arc::new(Value::Char(c))).collect(),
+            ))
+        }
+    );
+    builtin!(
+        registry,
+        "Text.fromCharList",
+        "[Char] -> Text",
+        |Value::Sequence(l)| {
+            Ok(Value::Text(
+                l.iter()
+                    .map(|c| match &**c {
+                        Value::Char(c) => Ok(*c),
+                        c => Err(RuntimeError::UnexpectedValue(
+                            "Char".to_owned(),
+                            Box::new(c.clone()),
+                        )),
+                    })
+                    .collect::<Result<String, RuntimeError>>()?,
+            ))
+        }
+    );
+    builtin!(registry, "Text.toUtf8", "Text -> Bytes", |Value::Text(
+        t,
+    )| {
+        Ok(Value::Bytes(t.bytes().collect()))
+    });
+    builtin!(
+        registry,
+        "Text.fromUtf8",
+        "Bytes -> Text",
+        |Value::Bytes(raw)| {
+            String::from_utf8(raw.iter().cloned().collect())
+                .map(Value::Text)
+                .map_err(|_| {
+                    RuntimeError::UnexpectedValue(
+                        "utf-8 bytes".to_owned(),
+                        Box::new(Value::Bytes(raw.clone())),
+                    )
+                })
+        }
+    );
+
+    builtin!(registry, "Char.toNat", "Char -> Nat", |Value::Char(c)| {
+        Ok(Value::Nat(*c as u64))
+    });
+    builtin!(registry, "Char.fromNat", "Nat -> Char", |Value::Nat(n)| {
+        match std::char::from_u32(*n as u32) {
+            Some(c) if *n <= std::u32::MAX as u64 => Ok(Value::Char(c)),
+            _ => Err(RuntimeError::UnexpectedValue(
+                "a unicode code point".to_owned(),
+                Box::new(Value::Nat(*n)),
+            )),
+        }
+    });
+}
@@ -0,0 +1,157 @@
+use super::BuiltinRegistry;
+use crate::error::RuntimeError;
+use crate::types::{Reference, Value};
+use im::Vector;
+use std::cmp::Ordering;
+use std::sync::Arc;
+
+// A constructor with no arguments is a `Constructor`, and one with arguments
+// is a `PartialConstructor`, but for comparing they're the same thing.
+fn as_data(value: &Value) -> Option<(&Reference, usize, Option<&Vector<Arc<Value>>>)> {
+    match value {
+        Value::Constructor(r, n) => Some((r, *n, None)),
+        Value::PartialConstructor(r, n, args) => Some((r, *n, Some(args))),
+        _ => None,
+    }
+}
+
+fn data_args<'a>(args: Option<&'a Vector<Arc<Value>>>) -> impl Iterator<Item = &'a Arc<Value>> {
+    args.into_iter().flat_map(|args| args.iter())
+}
+
+fn incomparable(one: &Value, two: &Value) -> RuntimeError {
+    RuntimeError::Incomparable(Box::new(one.clone()), Box::new(two.clone()))
+}
+
+// Like the haskell runtime, floats are compared with `==`, so NaN isn't equal to itself.
+// Functions, continuations & requests can't be compared.
+pub fn equal(one: &Value, two: &Value) -> Result<bool, RuntimeError> {
+    use Value::*;
+    Ok(match (one, two) {
+        (Int(a), Int(b)) => a == b,
+        (Nat(a), Nat(b)) => a == b,
+        (Float(a), Float(b)) => a == b,
+        (Boolean(a), Boolean(b)) => a == b,
+        (Text(a), Text(b)) => a == b,
+        (Bytes(a), Bytes(b)) => a == b,
+        (Char(a), Char(b)) => a == b,
+        (Ref(a), Ref(b)) => a == b,
+        (TypeLink(a), TypeLink(b)) => a == b,
+        (TermLink(a), TermLink(b)) => a == b,
+        (Sequence(a), Sequence(b)) => {
+            if a.len() != b.len() {
+                return Ok(false);
+            }
+            for (a, b) in a.iter().zip(b.iter()) {
+                if !equal(a, b)? {
+                    return Ok(false);
+                }
+            }
+            true
+        }
+        _ => match (as_data(one), as_data(two)) {
+            (Some((r1, n1, args1)), Some((r2, n2, args2))) => {
+                if r1 != r2 || n1 != n2 {
+                    return Ok(false);
+                }
+                let (mut args1, mut args2) = (data_args(args1), data_args(args2));
+                loop {
+                    match (args1.next(), args2.next()) {
+                        (None, None) => return Ok(true),
+                        (Some(a), Some(b)) => {
+                            if !equal(a, b)? {
+                                return Ok(false);
+                            }
+                        }
+                        _ => return Ok(false),
+                    }
+                }
+            }
+            _ => return Err(incomparable(one, two)),
+        },
+    })
+}
+
+// The ordering the haskell runtime uses: data is ordered by type, then
+// constructor number, then arguments; lists, text & bytes are lexicographic.
+// Floats use haskell's `compare`, which puts NaN after everything.
+pub fn compare(one: &Value, two: &Value) -> Result<Ordering, RuntimeError> {
+    use Value::*;
+    Ok(match (one, two) {
+        (Int(a), Int(b)) => a.cmp(b),
+        (Nat(a), Nat(b)) => a.cmp(b),
+        (Float(a), Float(b)) => {
+            if a < b {
+                Ordering::Less
+            } else if a == b {
+                Ordering::Equal
+            } else {
+                Ordering::Greater
+            }
+        }
+        (Boolean(a), Boolean(b)) => a.cmp(b),
+        // utf-8 byte order is the same as code point order
+        (Text(a), Text(b)) => a.cmp(b),
+        (Bytes(a), Bytes(b)) => a.cmp(b),
+        (Char(a), Char(b)) => a.cmp(b),
+        (Ref(a), Ref(b)) => a.cmp(b),
+        (TypeLink(a), TypeLink(b)) => a.cmp(b),
+        (TermLink(a), TermLink(b)) => a.cmp(b),
+        (Sequence(a), Sequence(b)) => compare_all(a.iter(), b.iter())?,
+        _ => match (as_data(one), as_data(two)) {
+            (Some((r1, n1, args1)), Some((r2, n2, args2))) => match r1.cmp(r2).then(n1.cmp(&n2)) {
+                Ordering::Equal => compare_all(data_args(args1), data_args(args2))?,
+                ordering => ordering,
+            },
+            _ => return Err(incomparable(one, two)),
+        },
+    })
+}
+
+fn compare_all<'a, A, B>(mut one: A, mut two: B) -> Result<Ordering, RuntimeError>
+where
+    A: Iterator<Item = &'a Arc<Value>>,
+    B: Iterator<Item = &'a Arc<Value>>,
+{
+    loop {
+        match (one.next(), two.next()) {
+            (None, None) => return Ok(Ordering::Equal),
+            (None, Some(_)) => return Ok(Ordering::Less),
+            (Some(_), None) => return Ok(Ordering::Greater),
+            (Some(a), Some(b)) => match compare(a, b)? {
+                Ordering::Equal => (),
+                ordering => return Ok(ordering),
+            },
+        }
+    }
+}
+
+pub fn register(registry: &mut BuiltinRegistry) {
+    builtin!(registry, "Universal.==", "a -> a -> Boolean", |one, two| {
+        Ok(Value::Boolean(equal(one, two)?))
+    });
+    builtin!(registry, "Universal.>", "a -> a -> Boolean", |one, two| {
+        Ok(Value::Boolean(compare(one, two)? == Ordering::Greater))
+    });
+    builtin!(registry, "Universal.<", "a -> a -> Boolean", |one, two| {
+        Ok(Value::Boolean(compare(one, two)? == Ordering::Less))
+    });
+    builtin!(registry, "Universal.>=", "a -> a -> Boolean", |one, two| {
+        Ok(Value::Boolean(compare(one, two)? != Ordering::Less))
+    });
+    builtin!(registry, "Universal.<=", "a -> a -> Boolean", |one, two| {
+        Ok(Value::Boolean(compare(one, two)? != Ordering::Greater))
+    });
+    builtin!(
+        registry,
+        "Universal.compare",
+        "a -> a -> Int",
+        |one, two| {
+            Ok(Value::Int(match compare(one, two)? {
+                Ordering::Less => -1,
+                Ordering::Equal => 0,
+                Ordering::Greater => 1,
+            }))
+        }
+    );
+}
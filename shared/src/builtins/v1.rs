@@ -0,0 +1,183 @@
+// The builtins that unison v1 provides (from `Unison.Builtin` in the unison repo),
+// so we can tell which ones we're missing.
+pub const BUILTINS: &[&str] = &[
+    "Boolean.not",
+    "Bytes.++",
+    "Bytes.at",
+    "Bytes.decodeNat16be",
+    "Bytes.decodeNat16le",
+    "Bytes.decodeNat32be",
+    "Bytes.decodeNat32le",
+    "Bytes.decodeNat64be",
+    "Bytes.decodeNat64le",
+    "Bytes.drop",
+    "Bytes.empty",
+    "Bytes.encodeNat16be",
+    "Bytes.encodeNat16le",
+    "Bytes.encodeNat32be",
+    "Bytes.encodeNat32le",
+    "Bytes.encodeNat64be",
+    "Bytes.encodeNat64le",
+    "Bytes.flatten",
+    "Bytes.fromBase16",
+    "Bytes.fromBase32",
+    "Bytes.fromBase64",
+    "Bytes.fromBase64UrlUnpadded",
+    "Bytes.fromList",
+    "Bytes.size",
+    "Bytes.take",
+    "Bytes.toBase16",
+    "Bytes.toBase32",
+    "Bytes.toBase64",
+    "Bytes.toBase64UrlUnpadded",
+    "Bytes.toList",
+    "Char.fromNat",
+    "Char.toNat",
+    "Debug.watch",
+    "Float.*",
+    "Float.+",
+    "Float.-",
+    "Float./",
+    "Float.<",
+    "Float.<=",
+    "Float.==",
+    "Float.>",
+    "Float.>=",
+    "Float.abs",
+    "Float.acos",
+    "Float.acosh",
+    "Float.asin",
+    "Float.asinh",
+    "Float.atan",
+    "Float.atan2",
+    "Float.atanh",
+    "Float.ceiling",
+    "Float.cos",
+    "Float.cosh",
+    "Float.exp",
+    "Float.floor",
+    "Float.fromText",
+    "Float.log",
+    "Float.logBase",
+    "Float.max",
+    "Float.min",
+    "Float.pow",
+    "Float.round",
+    "Float.sin",
+    "Float.sinh",
+    "Float.sqrt",
+    "Float.tan",
+    "Float.tanh",
+    "Float.toText",
+    "Float.truncate",
+    "Int.*",
+    "Int.+",
+    "Int.-",
+    "Int./",
+    "Int.<",
+    "Int.<=",
+    "Int.==",
+    "Int.>",
+    "Int.>=",
+    "Int.and",
+    "Int.complement",
+    "Int.fromText",
+    "Int.increment",
+    "Int.isEven",
+    "Int.isOdd",
+    "Int.leadingZeros",
+    "Int.mod",
+    "Int.negate",
+    "Int.or",
+    "Int.popCount",
+    "Int.pow",
+    "Int.shiftLeft",
+    "Int.shiftRight",
+    "Int.signum",
+    "Int.toFloat",
+    "Int.toText",
+    "Int.trailingZeros",
+    "Int.truncate0",
+    "Int.xor",
+    "List.++",
+    "List.+:",
+    "List.:+",
+    "List.at",
+    "List.cons",
+    "List.drop",
+    "List.empty",
+    "List.insert",
+    "List.replace",
+    "List.reverse",
+    "List.size",
+    "List.slice",
+    "List.snoc",
+    "List.take",
+    "List.uncons",
+    "List.unsnoc",
+    "Nat.*",
+    "Nat.+",
+    "Nat./",
+    "Nat.<",
+    "Nat.<=",
+    "Nat.==",
+    "Nat.>",
+    "Nat.>=",
+    "Nat.and",
+    "Nat.complement",
+    "Nat.drop",
+    "Nat.fromText",
+    "Nat.increment",
+    "Nat.isEven",
+    "Nat.isOdd",
+    "Nat.leadingZeros",
+    "Nat.mod",
+    "Nat.or",
+    "Nat.popCount",
+    "Nat.pow",
+    "Nat.shiftLeft",
+    "Nat.shiftRight",
+    "Nat.sub",
+    "Nat.toFloat",
+    "Nat.toInt",
+    "Nat.toText",
+    "Nat.trailingZeros",
+    "Nat.xor",
+    "Text.!=",
+    "Text.++",
+    "Text.<",
+    "Text.<=",
+    "Text.==",
+    "Text.>",
+    "Text.>=",
+    "Text.drop",
+    "Text.empty",
+    "Text.fromCharList",
+    "Text.fromUtf8",
+    "Text.repeat",
+    "Text.size",
+    "Text.take",
+    "Text.toCharList",
+    "Text.toUtf8",
+    "Text.uncons",
+    "Text.unsnoc",
+    "Universal.<",
+    "Universal.<=",
+    "Universal.==",
+    "Universal.>",
+    "Universal.>=",
+    "Universal.compare",
+    "bug",
+    "crypto.HashAlgorithm.Blake2b_256",
+    "crypto.HashAlgorithm.Blake2b_512",
+    "crypto.HashAlgorithm.Blake2s_256",
+    "crypto.HashAlgorithm.Sha2_256",
+    "crypto.HashAlgorithm.Sha2_512",
+    "crypto.HashAlgorithm.Sha3_256",
+    "crypto.HashAlgorithm.Sha3_512",
+    "crypto.hash",
+    "crypto.hashBytes",
+    "crypto.hmac",
+    "crypto.hmacBytes",
+    "todo",
+];
@@ -1,68 +1,336 @@
+// Checking that a value (usually from an FFI handler) has the type that
+// the code receiving it expects.
+//
+// Data types are checked against their declarations in the env, with type
+// variables bound to the type's arguments. A type variable that isn't bound
+// (e.g. the `a` in `forall a. a -> a`) accepts anything. Functions can't be
+// checked without running them, so any function value is accepted for an arrow.
+//
+// Each error starts with the path to the part of the value that's wrong, like
+// `$.Some.0[2]` for the third item of the list in a `Some`.
 use crate::types::*;
 
 const UNIT_HASH: &'static str = "568rsi7o3ghq8mmbea2sf8msdk20ohasob5s2rvjtqg2lr0vs39l1hm98urrjemsr3vo3fa52pibqu0maluq7g8sfg3h5f5re6vitj8";
 // const TUPLE_HASH: &'static str = "onbcm0qctbnuctpm57tkc5p16b8gfke8thjf19p4r4laokji0b606rd0frnhj103qb90lve3fohkoc1eda70491hot656s1m6kk3cn0";
 const FFI_HASH: &'static str = "ne22tbsth76tbte4ancb6p62khuv2c5jdegm4jk44o4n0nlou12inmjjldbjlo5hp66nqo8j55qglirsc1ecp9ea2ofcr0it9od3cl0";
 
-pub fn validate(
-    bindings: im::HashMap<String, ABT<Type>>,
+pub(crate) type Bindings = im::HashMap<String, ABT<Type>>;
+
+pub fn validate(env: &RuntimeEnv, typ: &ABT<Type>, val: &Value) -> Result<(), Vec<String>> {
+    let mut errors = vec![];
+    check(env, &Default::default(), typ, val, "$", &mut errors);
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+fn check(
+    env: &RuntimeEnv,
+    bindings: &Bindings,
     typ: &ABT<Type>,
     val: &Value,
-) -> Result<(), Vec<String>> {
+    path: &str,
+    errors: &mut Vec<String>,
+) {
     use ABT::*;
     match typ {
-        Var(_, _) => unimplemented!("Var"),
-        Cycle(content) => validate(bindings, &*content, val),
-        Abs(_sym, _, _content) => unimplemented!("Nope"),
+        // Bound types have already been substituted, so they don't refer to `bindings`
+        Var(sym, _) => match bindings.get(&sym.text) {
+            Some(bound) => check(env, &Bindings::new(), bound, val, path, errors),
+            // A type variable that's polymorphic here
+            None => (),
+        },
+        Cycle(content) => check(env, bindings, content, val, path, errors),
+        // The variable is introduced by a `forall`, so it's polymorphic inside
+        Abs(sym, _, content) => check(
+            env,
+            &bindings.without(&sym.text),
+            content,
+            val,
+            path,
+            errors,
+        ),
         Tm(typ) => match typ {
-            Type::Ref(Reference::Builtin(name)) => match (name.as_str(), val) {
-                ("Int", Value::Int(_)) => Ok(()),
-                ("Nat", Value::Nat(_)) => Ok(()),
-                ("Float", Value::Float(_)) => Ok(()),
-                ("Boolean", Value::Boolean(_)) => Ok(()),
-                ("Text", Value::Text(_)) => Ok(()),
-                ("Bytes", Value::Bytes(_)) => Ok(()),
-                ("Char", Value::Char(_)) => Ok(()),
-                _ => Err(vec![format!("Expected {}, found {:?}", name, val)]),
-            },
-            Type::Ref(Reference::DerivedId(id)) => {
-                if id.hash.0 == UNIT_HASH {
-                    match val {
-                        Value::Constructor(Reference::DerivedId(id), 0)
-                            if id.hash.0 == UNIT_HASH =>
-                        {
-                            Ok(())
+            Type::Ann(inner, _) => check(env, bindings, inner, val, path, errors),
+            Type::Effect(_, inner) => check(env, bindings, inner, val, path, errors),
+            Type::Forall(inner) => check(env, bindings, inner, val, path, errors),
+            Type::IntroOuter(inner) => check(env, bindings, inner, val, path, errors),
+            Type::Effects(_) => errors.push(format!(
+                "{}: Expected a value, but the type is a set of effects",
+                path
+            )),
+            Type::Arrow(_, _) => {
+                if !is_function(env, val) {
+                    errors.push(format!("{}: Expected a function, found {:?}", path, val))
+                }
+            }
+            Type::Ref(_) | Type::App(_, _) => {
+                let (head, args) = unapply(typ);
+                match &head {
+                    // A type-level lambda, applied
+                    Abs(sym, _, inner) if !args.is_empty() => {
+                        let mut args = args.iter().map(|arg| substitute(bindings, arg));
+                        let bindings = bindings.update(sym.text.clone(), args.next().unwrap());
+                        let mut applied = (**inner).clone();
+                        for arg in args {
+                            applied = ABT::Tm(Type::App(Box::new(applied), Box::new(arg)));
+                        }
+                        check(env, &bindings, &applied, val, path, errors)
+                    }
+                    Tm(Type::Ref(Reference::Builtin(name))) => {
+                        check_builtin(env, bindings, name, &args, val, path, errors)
+                    }
+                    Tm(Type::Ref(reference @ Reference::DerivedId(_))) => {
+                        let args: Vec<ABT<Type>> =
+                            args.iter().map(|arg| substitute(bindings, arg)).collect();
+                        check_data(env, reference, &args, val, path, errors)
+                    }
+                    // e.g. `f a` where `f` is a type variable
+                    Var(sym, _) => match bindings.get(&sym.text) {
+                        Some(bound) => {
+                            let mut applied = bound.clone();
+                            for arg in args {
+                                applied = ABT::Tm(Type::App(
+                                    Box::new(applied),
+                                    Box::new(substitute(bindings, &arg)),
+                                ));
+                            }
+                            check(env, &Bindings::new(), &applied, val, path, errors)
+                        }
+                        None => (),
+                    },
+                    other => {
+                        let mut applied = other.clone();
+                        for arg in args {
+                            applied = ABT::Tm(Type::App(Box::new(applied), Box::new(arg.clone())));
                         }
-                        _ => Err(vec!["Expected a unit".to_owned()]),
+                        check(env, bindings, &applied, val, path, errors)
                     }
-                } else {
-                    unimplemented!("Can't do custom types yet: {:?}", id)
                 }
             }
-            Type::Ann(inner, _) => validate(bindings, inner, val),
-            Type::Effect(_, inner) => validate(bindings, inner, val),
-            Type::Effects(_) => unimplemented!("Effects"),
-            Type::Forall(_) => unimplemented!("Forall"),
-            Type::IntroOuter(_) => unimplemented!("IntroOuter"),
-            Type::Arrow(_arg, _body) => match val {
-                _ => unimplemented!("Sorry, arrow not yet supported"), // ugh, I don't have enough type information right here
-            },
-            Type::App(inner, v) => match &**inner {
-                Tm(Type::Ref(Reference::DerivedId(id))) if id.hash.0 == FFI_HASH => {
-                    // this is my custom FFI type -- this is allowed without introspection...
-                    Ok(())
-                }
-                Abs(sym, _, inner) => validate(
-                    {
-                        let mut bindings = bindings.clone();
-                        bindings.insert(sym.text.clone(), (**v).clone());
-                        bindings
-                    },
-                    inner,
-                    val,
-                ),
-                nested => unreachable!("Arrow must have abs inside - {:?} <-> {:?}", nested, v),
-            },
         },
     }
 }
+
+// `Map Text Nat` -> (`Map`, [`Text`, `Nat`])
+pub(crate) fn unapply(typ: &Type) -> (ABT<Type>, Vec<ABT<Type>>) {
+    match typ {
+        Type::App(inner, arg) => {
+            let (head, mut args) = match &**inner {
+                ABT::Tm(inner) => unapply(inner),
+                other => (other.clone(), vec![]),
+            };
+            args.push((**arg).clone());
+            (head, args)
+        }
+        Type::Ann(inner, _) => match &**inner {
+            ABT::Tm(inner) => unapply(inner),
+            other => (other.clone(), vec![]),
+        },
+        other => (ABT::Tm(other.clone()), vec![]),
+    }
+}
+
+// Replaces the bound type variables, so the type means the same thing wherever it's used.
+pub(crate) fn substitute(bindings: &Bindings, typ: &ABT<Type>) -> ABT<Type> {
+    use ABT::*;
+    match typ {
+        Var(sym, _) => match bindings.get(&sym.text) {
+            Some(bound) => bound.clone(),
+            None => typ.clone(),
+        },
+        Cycle(inner) => Cycle(Box::new(substitute(bindings, inner))),
+        Abs(sym, usage, inner) => Abs(
+            sym.clone(),
+            *usage,
+            Box::new(substitute(&bindings.without(&sym.text), inner)),
+        ),
+        Tm(typ) => Tm(match typ {
+            Type::Ref(_) => typ.clone(),
+            Type::Arrow(a, b) => Type::Arrow(
+                Box::new(substitute(bindings, a)),
+                Box::new(substitute(bindings, b)),
+            ),
+            Type::Ann(inner, kind) => {
+                Type::Ann(Box::new(substitute(bindings, inner)), kind.clone())
+            }
+            Type::App(a, b) => Type::App(
+                Box::new(substitute(bindings, a)),
+                Box::new(substitute(bindings, b)),
+            ),
+            Type::Effect(a, b) => Type::Effect(
+                Box::new(substitute(bindings, a)),
+                Box::new(substitute(bindings, b)),
+            ),
+            Type::Effects(items) => {
+                Type::Effects(items.iter().map(|t| substitute(bindings, t)).collect())
+            }
+            Type::Forall(inner) => Type::Forall(Box::new(substitute(bindings, inner))),
+            Type::IntroOuter(inner) => Type::IntroOuter(Box::new(substitute(bindings, inner))),
+        }),
+    }
+}
+
+fn check_builtin(
+    env: &RuntimeEnv,
+    bindings: &Bindings,
+    name: &str,
+    args: &[ABT<Type>],
+    val: &Value,
+    path: &str,
+    errors: &mut Vec<String>,
+) {
+    match (name, args, val) {
+        ("Int", [], Value::Int(_)) => (),
+        ("Nat", [], Value::Nat(_)) => (),
+        ("Float", [], Value::Float(_)) => (),
+        ("Boolean", [], Value::Boolean(_)) => (),
+        ("Text", [], Value::Text(_)) => (),
+        ("Bytes", [], Value::Bytes(_)) => (),
+        ("Char", [], Value::Char(_)) => (),
+        ("Link.Term", [], Value::TermLink(_)) => (),
+        ("Link.Type", [], Value::TypeLink(_)) => (),
+        ("Sequence", [item], Value::Sequence(items)) => {
+            for (i, value) in items.iter().enumerate() {
+                check(
+                    env,
+                    bindings,
+                    item,
+                    value,
+                    &format!("{}[{}]", path, i),
+                    errors,
+                );
+            }
+        }
+        ("Sequence", [_], _) => errors.push(format!("{}: Expected a list, found {:?}", path, val)),
+        _ => errors.push(format!("{}: Expected {}, found {:?}", path, name, val)),
+    }
+}
+
+fn check_data(
+    env: &RuntimeEnv,
+    reference: &Reference,
+    args: &[ABT<Type>],
+    val: &Value,
+    path: &str,
+    errors: &mut Vec<String>,
+) {
+    let id = match reference {
+        Reference::DerivedId(id) => id,
+        Reference::Builtin(_) => unreachable!("Builtins are checked by check_builtin"),
+    };
+    if id.hash.0 == FFI_HASH {
+        // this is my custom FFI type -- this is allowed without introspection...
+        return;
+    }
+    let (number, fields) = match val {
+        Value::Constructor(r, number) if r == reference => (*number, vec![]),
+        Value::PartialConstructor(r, number, fields) if r == reference => {
+            (*number, fields.iter().cloned().collect())
+        }
+        _ => {
+            let expected = if id.hash.0 == UNIT_HASH {
+                "a unit".to_owned()
+            } else {
+                format!("a value of type {:?}", reference)
+            };
+            errors.push(format!("{}: Expected {}, found {:?}", path, expected, val));
+            return;
+        }
+    };
+    let decl = match env.types.get(id) {
+        Some(TypeDecl::Data(decl)) => decl,
+        // We don't have the declaration, so we can't look inside it
+        None => return,
+        Some(TypeDecl::Effect(_)) => {
+            errors.push(format!(
+                "{}: Expected a value, but {:?} is an ability",
+                path, reference
+            ));
+            return;
+        }
+    };
+    let (name, constructor_type) = match decl.constructors.get(number) {
+        Some(constructor) => constructor,
+        None => {
+            errors.push(format!(
+                "{}: {:?} doesn't have a constructor #{}",
+                path, reference, number
+            ));
+            return;
+        }
+    };
+    let mut bindings = Bindings::new();
+    for (sym, arg) in decl.bound.iter().zip(args) {
+        bindings.insert(sym.text.clone(), arg.clone());
+    }
+    let (field_types, _, _) = crate::ir_runtime::extract_args(strip_binders(constructor_type));
+    if fields.len() != field_types.len() {
+        errors.push(format!(
+            "{}: Expected {} to have {} fields, found {}",
+            path,
+            name.text,
+            field_types.len(),
+            fields.len()
+        ));
+        return;
+    }
+    for (i, (field_type, field)) in field_types.iter().zip(fields.iter()).enumerate() {
+        check(
+            env,
+            &bindings,
+            field_type,
+            field,
+            &format!("{}.{}.{}", path, name.text, i),
+            errors,
+        );
+    }
+}
+
+// `forall a b. a -> b -> Pair a b` -> `a -> b -> Pair a b`, so that the
+// declaration's type parameters aren't shadowed.
+pub(crate) fn strip_binders(typ: &ABT<Type>) -> &ABT<Type> {
+    match typ {
+        ABT::Abs(_, _, inner) => strip_binders(inner),
+        ABT::Tm(Type::Forall(inner)) => strip_binders(inner),
+        ABT::Tm(Type::IntroOuter(inner)) => strip_binders(inner),
+        _ => typ,
+    }
+}
+
+// The number of arguments a constructor takes, if we have its declaration.
+fn arity(env: &RuntimeEnv, reference: &Reference, number: usize) -> Option<usize> {
+    let decl = match reference {
+        Reference::DerivedId(id) => match env.types.get(id) {
+            Some(TypeDecl::Data(decl)) => decl,
+            Some(TypeDecl::Effect(decl)) => decl,
+            None => return None,
+        },
+        Reference::Builtin(_) => return None,
+    };
+    let (_, constructor_type) = decl.constructors.get(number)?;
+    let (args, _, _) = crate::ir_runtime::extract_args(strip_binders(constructor_type));
+    Some(args.len())
+}
+
+fn is_function(env: &RuntimeEnv, val: &Value) -> bool {
+    match val {
+        Value::PartialFnBody(_, _)
+        | Value::PartialFnBodyWithType(_, _, _)
+        | Value::CycleFnBody(_, _, _)
+        | Value::PartialNativeApp(_, _)
+        | Value::Continuation(_, _)
+        // A reference to a term or builtin function
+        | Value::Ref(_) => true,
+        // Constructors & ability operations that are still waiting for arguments
+        Value::Constructor(r, number) | Value::Request(r, number) => {
+            arity(env, r, *number).map_or(true, |arity| arity > 0)
+        }
+        Value::PartialConstructor(r, number, fields) => {
+            arity(env, r, *number).map_or(true, |arity| arity > fields.len())
+        }
+        _ => false,
+    }
+}
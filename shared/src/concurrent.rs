@@ -0,0 +1,844 @@
+// Threads, MVars, TVars & channels for unison programs, implementing the
+// `concurrent.Concurrent` ability (defined in `shared/concurrent.u`).
+//
+// Threads are green: each one is a task on a `Scheduler`, and its requests to
+// the ability bail out of the State like any other unhandled request. We then
+// hold on to the continuation, and resume it once the operation can go ahead,
+// e.g. when someone `put`s into the MVar it's `take`ing from. Since nothing here
+// needs real threads, programs behave the same natively and in the browser.
+//
+// The program is done when its main thread is; any other threads are dropped.
+// A failure in another thread only ends that thread, and is logged.
+// Handles (for threads, MVars, TVars & channels) are Nats, which `concurrent.u`
+// wraps up in types.
+use super::async_ffi::{AsyncFFI, ValueFuture};
+use super::builtins::Refs;
+use super::chrome_trace::Traces;
+use super::error::{Failure, RuntimeError};
+use super::ffi::FFI;
+use super::scheduler::{Event, Scheduler, TaskId};
+use super::state::{FullRequest, State};
+use super::types::*;
+use log::warn;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+const ABILITY: &str = "concurrent.Concurrent";
+
+// How many turns to take in `run_to_end` before checking on the async handlers
+pub const TURNS: usize = 10;
+
+pub enum Outcome {
+    // The main thread finished
+    Done(Arc<Value>),
+    // The main thread failed (including by deadlock)
+    Failed(Failure),
+    // The main thread was killed
+    Killed,
+    // These threads made requests the FFI couldn't handle synchronously (see `request`).
+    // If nothing else is `ready`, we're waiting on those or earlier ones to be resumed.
+    Pending { requests: Vec<TaskId>, ready: bool },
+}
+
+#[derive(Default)]
+struct MVar {
+    value: Option<Arc<Value>>,
+    // waiting for a value, and whether they only want to read it
+    takers: VecDeque<(TaskId, bool)>,
+    // waiting for it to be empty, with what they want to put in it
+    putters: VecDeque<(TaskId, Arc<Value>)>,
+}
+
+#[derive(Default)]
+struct Channel {
+    items: VecDeque<Arc<Value>>,
+    receivers: VecDeque<TaskId>,
+}
+
+pub struct Runtime {
+    pub scheduler: Scheduler,
+    // The ability's reference, and the names of its operations
+    ability: Option<(Reference, Vec<String>)>,
+    refs: Refs,
+    main: TaskId,
+    mvars: HashMap<u64, MVar>,
+    tvars: HashMap<u64, Arc<Value>>,
+    channels: HashMap<u64, Channel>,
+    next_handle: u64,
+    // threads parked on a request to the host, rather than to us
+    requests: HashSet<TaskId>,
+    // how deeply nested the current atomic section is
+    atomic: usize,
+}
+
+// The ability's reference, if it's in the env.
+pub fn ability(env: &RuntimeEnv) -> Option<Reference> {
+    find_ability(env).map(|(kind, _)| kind)
+}
+
+impl Runtime {
+    // `main` is the program's main thread
+    pub fn new(env: &RuntimeEnv, main: &State) -> Self {
+        let mut scheduler = Scheduler::new();
+        let main = scheduler.spawn(main);
+        // Otherwise a thread could get around its budget by forking another
+        scheduler.share_steps();
+        Runtime {
+            scheduler,
+            ability: find_ability(env),
+            refs: env.builtins.refs.clone(),
+            main,
+            mvars: HashMap::new(),
+            tvars: HashMap::new(),
+            channels: HashMap::new(),
+            next_handle: 0,
+            requests: HashSet::new(),
+            atomic: 0,
+        }
+    }
+
+    pub fn main(&self) -> TaskId {
+        self.main
+    }
+
+    // The instructions the threads have executed between them, including the ones the
+    // main thread's State had before.
+    pub fn steps(&self) -> usize {
+        self.scheduler.shared_steps().unwrap_or_default()
+    }
+
+    // The request a thread is waiting on the host for.
+    pub fn request(&self, id: TaskId) -> Option<&FullRequest> {
+        if self.requests.contains(&id) {
+            self.scheduler.request(id)
+        } else {
+            None
+        }
+    }
+
+    // Continues a thread that was waiting on the host, with the result of its request.
+    pub fn resume(&mut self, id: TaskId, value: Value) -> Result<(), String> {
+        if !self.requests.remove(&id) {
+            return Err(format!("Thread {} isn't waiting on a request", id));
+        }
+        self.scheduler.resume(id, value)
+    }
+
+    // Ends a thread that was waiting on the host, blaming its request for `error`.
+    pub fn fail(&mut self, id: TaskId, error: RuntimeError) -> Option<Failure> {
+        if !self.requests.remove(&id) {
+            return None;
+        }
+        self.scheduler.fail(id, error)
+    }
+
+    // Hands a thread's request over to someone else, e.g. `FFI::handle_request`. The
+    // thread is no longer ours, so it can't use the ability anymore.
+    pub fn hand_off(&mut self, id: TaskId) -> Option<FullRequest> {
+        if !self.requests.remove(&id) {
+            return None;
+        }
+        self.scheduler.take_request(id)
+    }
+
+    // Takes (up to) this many turns, handling the ability's requests as they come up.
+    pub fn run<T: FFI>(
+        &mut self,
+        env: &RuntimeEnv,
+        ffi: &mut T,
+        trace: &mut Traces,
+        turns: usize,
+    ) -> Outcome {
+        let kind = self.ability.as_ref().map(|(kind, _)| kind.clone());
+        let mut ffi = Bail(ffi, kind.clone());
+        let mut requests = vec![];
+        for _ in 0..turns {
+            let event = match self.scheduler.step(env, &mut ffi, trace) {
+                Some(event) => event,
+                None => break,
+            };
+            let failure = match event {
+                Event::Yielded(_) => continue,
+                Event::Done(id, value) if id == self.main => return Outcome::Done(value),
+                Event::Done(id, _) => {
+                    self.forget(id);
+                    continue;
+                }
+                Event::Failed(id, failure) => Some((id, failure)),
+                Event::Parked(id) => match self.scheduler.request(id) {
+                    Some(request) if Some(&request.0) == kind.as_ref() => {
+                        let (kind, number, args) =
+                            (request.0.clone(), request.1, request.2.clone());
+                        match self.handle(env, id, &kind, number, &args) {
+                            Ok(()) => None,
+                            Err(error) => self.scheduler.fail(id, error).map(|f| (id, f)),
+                        }
+                    }
+                    _ => {
+                        self.requests.insert(id);
+                        requests.push(id);
+                        None
+                    }
+                },
+            };
+            if let Some((id, failure)) = failure {
+                // Exiting ends the whole program, whichever thread does it
+                if id == self.main || matches!(*failure.error, RuntimeError::Exit(_)) {
+                    return Outcome::Failed(failure);
+                }
+                self.forget(id);
+                warn!("Thread {} failed: {}", id, failure);
+            }
+            if !self.scheduler.contains(self.main) {
+                return Outcome::Killed;
+            }
+        }
+        let ready = self.scheduler.has_ready();
+        if !ready && self.requests.is_empty() {
+            // Everyone's waiting on an MVar or channel, which nobody can fill
+            return match self.scheduler.fail(self.main, RuntimeError::Deadlock) {
+                Some(failure) => Outcome::Failed(failure),
+                None => Outcome::Killed,
+            };
+        }
+        Outcome::Pending { requests, ready }
+    }
+
+    fn handle(
+        &mut self,
+        env: &RuntimeEnv,
+        id: TaskId,
+        kind: &Reference,
+        number: usize,
+        args: &[Arc<Value>],
+    ) -> Result<(), RuntimeError> {
+        let name = self
+            .ability
+            .as_ref()
+            .and_then(|(_, ops)| ops.get(number).cloned())
+            .ok_or_else(|| RuntimeError::UnknownAbility(kind.clone(), number))?;
+        let args: Vec<&Value> = args.iter().map(|arg| &**arg).collect();
+        match (name.as_str(), args.as_slice()) {
+            ("fork_", [thunk]) => {
+                let thread = self.fork(env, id, thunk)?;
+                self.wake(id, Value::Nat(thread as u64));
+            }
+            ("kill_", [Value::Nat(thread)]) => {
+                let thread = *thread as TaskId;
+                self.scheduler.cancel(thread);
+                self.forget(thread);
+                if thread != id {
+                    self.wake(id, self.unit());
+                }
+            }
+            // Resuming puts us at the back of the queue
+            ("yield_", []) => self.wake(id, self.unit()),
+            ("myThreadId_", []) => self.wake(id, Value::Nat(id as u64)),
+            ("beginAtomic_", []) => {
+                self.atomic += 1;
+                self.scheduler.pin(id);
+                self.wake(id, self.unit());
+            }
+            ("endAtomic_", []) => {
+                self.atomic = self.atomic.saturating_sub(1);
+                if self.atomic == 0 {
+                    self.scheduler.unpin();
+                }
+                self.wake(id, self.unit());
+            }
+
+            ("newMVar_", []) => {
+                let handle = self.handle_for(|runtime, handle| {
+                    runtime.mvars.insert(handle, MVar::default());
+                });
+                self.wake(id, Value::Nat(handle));
+            }
+            ("take_", [Value::Nat(handle)]) => {
+                let mvar = self.mvar(*handle)?;
+                match mvar.value.take() {
+                    Some(value) => {
+                        if let Some((putter, next)) = mvar.putters.pop_front() {
+                            self.wake(putter, self.unit());
+                            self.fill(*handle, next);
+                        }
+                        self.wake(id, (*value).clone());
+                    }
+                    None => mvar.takers.push_back((id, false)),
+                }
+            }
+            ("read_", [Value::Nat(handle)]) => {
+                let mvar = self.mvar(*handle)?;
+                match &mvar.value {
+                    Some(value) => {
+                        let value = (**value).clone();
+                        self.wake(id, value);
+                    }
+                    None => mvar.takers.push_back((id, true)),
+                }
+            }
+            ("put_", [Value::Nat(handle), value]) => {
+                let value = Arc::new((*value).clone());
+                let mvar = self.mvar(*handle)?;
+                if mvar.value.is_some() {
+                    mvar.putters.push_back((id, value));
+                } else {
+                    self.fill(*handle, value);
+                    self.wake(id, self.unit());
+                }
+            }
+            ("tryTake_", [Value::Nat(handle)]) => {
+                let mvar = self.mvar(*handle)?;
+                let result = match mvar.value.take() {
+                    Some(value) => {
+                        if let Some((putter, next)) = mvar.putters.pop_front() {
+                            self.wake(putter, self.unit());
+                            self.fill(*handle, next);
+                        }
+                        self.refs.some(value)
+                    }
+                    None => self.refs.none(),
+                };
+                self.wake(id, result);
+            }
+            ("tryPut_", [Value::Nat(handle), value]) => {
+                let value = Arc::new((*value).clone());
+                let full = self.mvar(*handle)?.value.is_some();
+                if !full {
+                    self.fill(*handle, value);
+                }
+                self.wake(id, Value::Boolean(!full));
+            }
+
+            ("newTVar_", [value]) => {
+                let value = Arc::new((*value).clone());
+                let handle = self.handle_for(|runtime, handle| {
+                    runtime.tvars.insert(handle, value);
+                });
+                self.wake(id, Value::Nat(handle));
+            }
+            ("readTVar_", [Value::Nat(handle)]) => {
+                let value = (**self.tvar(*handle)?).clone();
+                self.wake(id, value);
+            }
+            ("writeTVar_", [Value::Nat(handle), value]) => {
+                *self.tvar(*handle)? = Arc::new((*value).clone());
+                self.wake(id, self.unit());
+            }
+
+            ("newChannel_", []) => {
+                let handle = self.handle_for(|runtime, handle| {
+                    runtime.channels.insert(handle, Channel::default());
+                });
+                self.wake(id, Value::Nat(handle));
+            }
+            ("send_", [Value::Nat(handle), value]) => {
+                let value = (*value).clone();
+                let channel = self.channel(*handle)?;
+                match channel.receivers.pop_front() {
+                    Some(receiver) => self.wake(receiver, value),
+                    None => channel.items.push_back(Arc::new(value)),
+                }
+                self.wake(id, self.unit());
+            }
+            ("receive_", [Value::Nat(handle)]) => {
+                let channel = self.channel(*handle)?;
+                match channel.items.pop_front() {
+                    Some(value) => self.wake(id, (*value).clone()),
+                    None => channel.receivers.push_back(id),
+                }
+            }
+            ("tryReceive_", [Value::Nat(handle)]) => {
+                let result = match self.channel(*handle)?.items.pop_front() {
+                    Some(value) => self.refs.some(value),
+                    None => self.refs.none(),
+                };
+                self.wake(id, result);
+            }
+
+            (name, args) => {
+                return Err(RuntimeError::InvalidBuiltinArgs(
+                    format!("{}.{}", ABILITY, name),
+                    args.iter().map(|arg| (*arg).clone()).collect(),
+                ))
+            }
+        };
+        Ok(())
+    }
+
+    // Starts a thread that calls `thunk` with `()`. It can raise anything its parent
+    // could, and its instructions count against the same budget.
+    fn fork(
+        &mut self,
+        env: &RuntimeEnv,
+        parent: TaskId,
+        thunk: &Value,
+    ) -> Result<TaskId, RuntimeError> {
+        let unit = ABT::Tm(Type::Ref(self.refs.unit.clone()));
+        let (fnid, bindings, arg, effects) = match thunk {
+            Value::PartialFnBodyWithType(fnid, bindings, typ) => {
+                let (args, effects, _) = crate::ir_runtime::extract_args(typ);
+                let arg = args.into_iter().next().unwrap_or(unit);
+                (
+                    *fnid,
+                    bindings.clone(),
+                    arg,
+                    crate::state::build_effects_map(effects)?,
+                )
+            }
+            Value::PartialFnBody(fnid, bindings) => (*fnid, bindings.clone(), unit, HashMap::new()),
+            _ => {
+                return Err(RuntimeError::UnexpectedValue(
+                    "a delayed computation".to_owned(),
+                    Box::new(thunk.clone()),
+                ))
+            }
+        };
+        let mut all_effects = self.scheduler.effects(parent).cloned().unwrap_or_default();
+        all_effects.extend(effects);
+        let mut state = State::lambda(env, fnid, bindings, self.unit(), &arg, all_effects)
+            .map_err(|error| RuntimeError::Internal(format!("Can't fork: {:?}", error)))?;
+        if let Some(budget) = self.scheduler.budget(parent) {
+            state.budget = budget.clone();
+        }
+        Ok(self.scheduler.spawn(&state))
+    }
+
+    // Makes `value` available to the MVar's takers: readers all get it, and then
+    // the first taker takes it, letting the next putter in.
+    fn fill(&mut self, handle: u64, mut value: Arc<Value>) {
+        loop {
+            let mvar = match self.mvars.get_mut(&handle) {
+                Some(mvar) => mvar,
+                None => return,
+            };
+            match mvar.takers.pop_front() {
+                Some((reader, true)) => self.wake(reader, (*value).clone()),
+                Some((taker, false)) => {
+                    let next = mvar.putters.pop_front();
+                    self.wake(taker, (*value).clone());
+                    match next {
+                        Some((putter, next)) => {
+                            self.wake(putter, self.unit());
+                            value = next;
+                        }
+                        None => return,
+                    }
+                }
+                None => {
+                    mvar.value = Some(value);
+                    return;
+                }
+            }
+        }
+    }
+
+    // A thread is done (or gone), so it isn't waiting on anything anymore
+    fn forget(&mut self, id: TaskId) {
+        self.requests.remove(&id);
+        for mvar in self.mvars.values_mut() {
+            mvar.takers.retain(|(taker, _)| *taker != id);
+            mvar.putters.retain(|(putter, _)| *putter != id);
+        }
+        for channel in self.channels.values_mut() {
+            channel.receivers.retain(|receiver| *receiver != id);
+        }
+    }
+
+    fn wake(&mut self, id: TaskId, value: Value) {
+        // Threads are forgotten once they're gone, so they're always still parked
+        let _ = self.scheduler.resume(id, value);
+    }
+
+    fn handle_for<F: FnOnce(&mut Self, u64)>(&mut self, init: F) -> u64 {
+        let handle = self.next_handle;
+        self.next_handle += 1;
+        init(self, handle);
+        handle
+    }
+
+    fn mvar(&mut self, handle: u64) -> Result<&mut MVar, RuntimeError> {
+        self.mvars
+            .get_mut(&handle)
+            .ok_or_else(|| unknown_handle("MVar", handle))
+    }
+
+    fn tvar(&mut self, handle: u64) -> Result<&mut Arc<Value>, RuntimeError> {
+        self.tvars
+            .get_mut(&handle)
+            .ok_or_else(|| unknown_handle("TVar", handle))
+    }
+
+    fn channel(&mut self, handle: u64) -> Result<&mut Channel, RuntimeError> {
+        self.channels
+            .get_mut(&handle)
+            .ok_or_else(|| unknown_handle("Channel", handle))
+    }
+
+    fn unit(&self) -> Value {
+        Value::Constructor(self.refs.unit.clone(), 0)
+    }
+}
+
+fn unknown_handle(kind: &str, handle: u64) -> RuntimeError {
+    RuntimeError::Internal(format!("No {} with handle {}", kind, handle))
+}
+
+// Like `async_ffi::run_to_end`, but the program can fork threads. While some threads
+// wait on async handlers, the others keep running.
+// Requests without an async handler are handed to `handle_request`; if it's the
+// main thread's, the run ends there (with `None`), like it does for `State::run_to_end`.
+// Afterwards, the state's `steps` are the ones every thread used.
+pub async fn run_to_end<T: AsyncFFI>(
+    env: &RuntimeEnv,
+    state: &mut State<'_>,
+    ffi: &mut T,
+    trace: &mut Traces,
+) -> Result<Option<Arc<Value>>, Failure> {
+    let mut runtime = Runtime::new(env, state);
+    let result = run_threads(env, &mut runtime, ffi, trace).await;
+    state.steps = runtime.steps();
+    result
+}
+
+async fn run_threads<T: AsyncFFI>(
+    env: &RuntimeEnv,
+    runtime: &mut Runtime,
+    ffi: &mut T,
+    trace: &mut Traces,
+) -> Result<Option<Arc<Value>>, Failure> {
+    let mut waiting = vec![];
+    loop {
+        let (requests, ready) = match runtime.run(env, ffi, trace, TURNS) {
+            Outcome::Done(value) => return Ok(Some(value)),
+            Outcome::Failed(failure) => return Err(failure),
+            Outcome::Killed => return Ok(None),
+            Outcome::Pending { requests, ready } => (requests, ready),
+        };
+        for id in requests {
+            let future = match runtime.request(id) {
+                Some(request) => {
+                    ffi.handle_request_async(&request.5, &request.0, request.1, &request.2)
+                }
+                None => continue,
+            };
+            match future {
+                Some(future) => waiting.push((id, future)),
+                None => {
+                    if let Some(request) = runtime.hand_off(id) {
+                        ffi.handle_request(request);
+                    }
+                    if id == runtime.main() {
+                        return Ok(None);
+                    }
+                }
+            }
+        }
+        let mut wait = !ready;
+        while let Some((id, result)) = next_completed(&mut waiting, wait).await {
+            wait = false;
+            if let Some(failure) = finish(runtime, id, result) {
+                return Err(failure);
+            }
+        }
+    }
+}
+
+// Resumes a thread with the result of its async handler. You get the failure
+// if the handler failed on the main thread.
+pub fn finish(runtime: &mut Runtime, id: TaskId, result: Result<Value, String>) -> Option<Failure> {
+    let message = match result {
+        Ok(value) => {
+            let _ = runtime.resume(id, value);
+            return None;
+        }
+        Err(message) => message,
+    };
+    let (kind, number) = match runtime.request(id) {
+        Some(request) => (request.0.clone(), request.1),
+        None => return None,
+    };
+    let failure = runtime.fail(id, RuntimeError::AsyncFailure(kind, number, message))?;
+    if id == runtime.main() {
+        return Some(failure);
+    }
+    warn!("Thread {} failed: {}", id, failure);
+    None
+}
+
+// The first of the futures to finish, along with its thread, which is removed from
+// the list. If `wait` is false, we only give the executor one chance to run the
+// others, and you get `None` if none of them are done by then.
+pub fn next_completed(
+    futures: &mut Vec<(TaskId, ValueFuture)>,
+    wait: bool,
+) -> impl Future<Output = Option<(TaskId, Result<Value, String>)>> + '_ {
+    NextCompleted {
+        futures,
+        wait,
+        yielded: false,
+    }
+}
+
+struct NextCompleted<'f> {
+    futures: &'f mut Vec<(TaskId, ValueFuture)>,
+    wait: bool,
+    yielded: bool,
+}
+
+impl<'f> Future for NextCompleted<'f> {
+    type Output = Option<(TaskId, Result<Value, String>)>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        for i in 0..this.futures.len() {
+            if let Poll::Ready(result) = this.futures[i].1.as_mut().poll(cx) {
+                let (id, _) = this.futures.remove(i);
+                return Poll::Ready(Some((id, result)));
+            }
+        }
+        if this.futures.is_empty() || (!this.wait && this.yielded) {
+            return Poll::Ready(None);
+        }
+        if !this.wait {
+            this.yielded = true;
+            cx.waker().wake_by_ref();
+        }
+        Poll::Pending
+    }
+}
+
+// Bails out on the ability's requests, so we get their continuations.
+struct Bail<'f, T: FFI>(&'f mut T, Option<Reference>);
+
+impl<'f, T: FFI> FFI for Bail<'f, T> {
+    fn handle_request_sync(
+        &mut self,
+        typ: &ABT<Type>,
+        kind: &Reference,
+        number: usize,
+        args: &Vec<Arc<Value>>,
+    ) -> Result<Option<Value>, RuntimeError> {
+        if Some(kind) == self.1.as_ref() {
+            return Ok(None);
+        }
+        self.0.handle_request_sync(typ, kind, number, args)
+    }
+
+    fn check_request(
+        &mut self,
+        kind: &Reference,
+        number: usize,
+        args: &Vec<Arc<Value>>,
+    ) -> Result<(), RuntimeError> {
+        // The ability is handled by us rather than the host, so it's not up to the
+        // host's policy, and doesn't count as a call to it
+        if Some(kind) == self.1.as_ref() {
+            return Ok(());
+        }
+        self.0.check_request(kind, number, args)
+    }
+
+    fn handle_request(&mut self, request: FullRequest) {
+        self.0.handle_request(request)
+    }
+
+    fn handles(&self, kind: &Reference) -> bool {
+        Some(kind) == self.1.as_ref() || self.0.handles(kind)
+    }
+}
+
+// The ability's constructors are named after it, e.g. `concurrent.Concurrent.fork_`.
+// We also match on suffixes, in case it was added under another namespace.
+fn find_ability(env: &RuntimeEnv) -> Option<(Reference, Vec<String>)> {
+    for (id, decl) in env.types.iter() {
+        let data = match decl {
+            TypeDecl::Effect(data) => data,
+            _ => continue,
+        };
+        let mut ops = vec![];
+        for (sym, _) in data.constructors.iter() {
+            let (prefix, name) = match sym.text.rfind('.') {
+                Some(idx) => (&sym.text[..idx], &sym.text[idx + 1..]),
+                None => break,
+            };
+            if prefix != ABILITY && !prefix.ends_with(&format!(".{}", ABILITY)) {
+                break;
+            }
+            ops.push(name.to_owned());
+        }
+        if !ops.is_empty() && ops.len() == data.constructors.len() {
+            return Some((Reference::DerivedId(id.clone()), ops));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::budget::Budget;
+    use crate::policy::{Policy, PolicyFFI};
+
+    struct NoFFI;
+
+    impl FFI for NoFFI {
+        fn handle_request_sync(
+            &mut self,
+            _typ: &ABT<Type>,
+            _kind: &Reference,
+            _number: usize,
+            _args: &Vec<Arc<Value>>,
+        ) -> Result<Option<Value>, RuntimeError> {
+            Ok(None)
+        }
+
+        fn handle_request(&mut self, _request: FullRequest) {}
+
+        fn handles(&self, _kind: &Reference) -> bool {
+            false
+        }
+    }
+
+    const OPS: &[&str] = &["fork_", "newMVar_", "take_", "put_"];
+
+    fn symbol(text: &str) -> Symbol {
+        Symbol {
+            num: 0,
+            text: text.to_owned(),
+            unique: 0,
+        }
+    }
+
+    fn request(kind: &Reference, op: &str, arity: usize) -> IR {
+        let number = OPS.iter().position(|name| *name == op).unwrap();
+        if arity == 0 {
+            IR::Value(Value::Request(kind.clone(), number))
+        } else {
+            IR::Value(Value::RequestWithArgs(kind.clone(), number, arity, vec![]))
+        }
+    }
+
+    // Forks `workers` threads that each spin for `spin` instructions & then put their
+    // number into an MVar, and takes the first one. Returns the env, the main term &
+    // its effects.
+    fn forking(workers: usize, spin: usize) -> (RuntimeEnv, Id, HashMap<String, ABT<Type>>) {
+        let mut env = RuntimeEnv::new();
+        let id = Id::from_string("concurrent");
+        let kind = Reference::DerivedId(id.clone());
+        let nat = || ABT::Tm(Type::Ref(Reference::Builtin("Nat".to_owned())));
+        let effect = ABT::Tm(Type::Ref(kind.clone()));
+        let unit = ABT::Tm(Type::Ref(env.builtins.refs.unit.clone()));
+        let arrow = |arg: ABT<Type>, result: ABT<Type>| {
+            ABT::Tm(Type::Arrow(Box::new(arg), Box::new(result)))
+        };
+        let raises = |result: ABT<Type>| {
+            ABT::Tm(Type::Effect(
+                Box::new(ABT::Tm(Type::Effects(vec![effect.clone()]))),
+                Box::new(result),
+            ))
+        };
+        let types = vec![
+            arrow(arrow(unit.clone(), unit.clone()), raises(nat())),
+            raises(nat()),
+            arrow(nat(), raises(nat())),
+            arrow(nat(), arrow(nat(), raises(unit))),
+        ];
+        let constructors = OPS
+            .iter()
+            .zip(types)
+            .map(|(op, typ)| (symbol(&format!("{}.{}", ABILITY, op)), typ))
+            .collect();
+        env.types.insert(
+            id,
+            TypeDecl::Effect(DataDecl {
+                modifier: Modifier::Structural,
+                bound: vec![],
+                constructors,
+            }),
+        );
+        for worker in 0..workers {
+            let mut body = vec![IR::Pop];
+            for n in 0..spin {
+                body.push(IR::Value(Value::Nat(n as u64)));
+                body.push(IR::Pop);
+            }
+            body.extend(vec![
+                request(&kind, "put_", 2),
+                IR::Value(Value::Nat(0)),
+                IR::Call,
+                IR::Value(Value::Nat(worker as u64)),
+                IR::Call,
+            ]);
+            env.anon_fns.push((Id::from_string("worker"), body));
+        }
+        let mut cmds = vec![request(&kind, "newMVar_", 0), IR::Pop];
+        for worker in 0..workers {
+            cmds.extend(vec![
+                request(&kind, "fork_", 1),
+                IR::Fn(worker, vec![]),
+                IR::Call,
+                IR::Pop,
+            ]);
+        }
+        cmds.extend(vec![
+            request(&kind, "take_", 1),
+            IR::Value(Value::Nat(0)),
+            IR::Call,
+        ]);
+        let main = Id::from_string("main");
+        env.terms.insert(main.clone(), (cmds, nat()));
+        let effects = crate::state::build_effects_map(vec![effect].into_iter().collect()).unwrap();
+        (env, main, effects)
+    }
+
+    fn run<T: FFI>(env: &RuntimeEnv, state: &State, ffi: &mut T) -> Outcome {
+        let mut runtime = Runtime::new(env, state);
+        runtime.run(env, ffi, &mut Traces::new(), 1_000)
+    }
+
+    #[test]
+    fn policies_dont_apply_to_the_ability() {
+        let (env, main, effects) = forking(1, 0);
+        let state = State::new_value(&env, main, false, effects);
+        let policy = Policy {
+            allow: Some(vec!["native.IO".to_owned()]),
+            max_calls: Some(0),
+            ..Default::default()
+        };
+        let mut ffi = PolicyFFI::new(NoFFI, policy, &env);
+        match run(&env, &state, &mut ffi) {
+            Outcome::Done(value) => assert_eq!(*value, Value::Nat(0)),
+            Outcome::Failed(failure) => panic!("Failed: {}", failure),
+            _ => panic!("Expected the main thread to finish"),
+        }
+        assert_eq!(ffi.calls, 0);
+    }
+
+    #[test]
+    fn forked_threads_share_the_budget() {
+        let (env, main, effects) = forking(3, 50);
+        let mut state = State::new_value(&env, main.clone(), false, effects.clone());
+        // enough for any one of the threads, but not all of them
+        state.budget = Budget::steps(150);
+        match run(&env, &state, &mut NoFFI) {
+            // the workers run out between them, so nobody puts into the MVar
+            Outcome::Failed(failure) => match *failure.error {
+                RuntimeError::OutOfFuel(_) | RuntimeError::Deadlock => (),
+                error => panic!("Wrong error {:?}", error),
+            },
+            Outcome::Done(value) => panic!("Expected to run out of fuel, got {:?}", value),
+            _ => panic!("Expected the main thread to fail"),
+        }
+
+        let mut state = State::new_value(&env, main, false, effects);
+        state.budget = Budget::steps(400);
+        match run(&env, &state, &mut NoFFI) {
+            Outcome::Done(value) => assert_eq!(*value, Value::Nat(0)),
+            Outcome::Failed(failure) => panic!("Failed: {}", failure),
+            _ => panic!("Expected the main thread to finish"),
+        }
+    }
+}
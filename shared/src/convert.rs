@@ -45,12 +45,26 @@ pub fn to_json_type(typ: ABT<Type>) -> serde_json::Value {
 
 pub trait ConvertibleArg<T: Sized> {
     fn as_f64(&self) -> Option<f64>;
+    fn as_bool(&self) -> Option<bool>;
     fn as_string(&self) -> Option<String>;
     fn as_list(&self) -> Option<Vec<T>>;
     fn is_empty(&self) -> bool;
 }
 
+// Builds a value of type `typ` (applied to `args`) from a js or command-line value:
+// - `Nat`, `Int` & `Float` from numbers, `Boolean` from booleans, `Text` from strings,
+//   and `Char` from the first character of a string
+// - lists from arrays, and `Bytes` from arrays of numbers from 0 to 255
+// - `()` from null/undefined, and `Optional a` from null/undefined for `None`,
+//   or an `a` for `Some`
+// - tuples from arrays of their items, e.g. `[1, "one"]` for `(Nat, Text)`
+// - other data types from an array of the constructor's name followed by its
+//   fields, like `["Node", ["Leaf"], 2, ["Leaf"]]`. A constructor without fields
+//   can be just its name (`"Leaf"`), and if the type only has one constructor
+//   (like a record), the name can be left off (`[1, 2]` for `Point 1 2`).
+//   The name can be fully-qualified, or just the last segment.
 pub fn convert_arg<'a, T>(
+    env: &RuntimeEnv,
     arg: T,
     typ: &'a ABT<Type>,
     mut args: Vec<&'a ABT<Type>>,
@@ -64,15 +78,15 @@ where
         Var(_, _) => Ok(Value::Int(10000)),
         Tm(inner) => match inner {
             Arrow(_, _) => Err("Functions aren't yet supported".to_owned()),
-            Ann(inner, _) => convert_arg(arg, inner, args),
+            Ann(inner, _) => convert_arg(env, arg, inner, args),
             App(inner, targ) => {
                 args.insert(0, targ);
-                convert_arg(arg, inner, args)
+                convert_arg(env, arg, inner, args)
             }
             Effect(_, _) => Err("Effect types not yet supported".to_owned()),
             Effects(_) => Err("Effects not supported".to_owned()),
-            Forall(inner) => convert_arg(arg, inner, args),
-            IntroOuter(inner) => convert_arg(arg, inner, args),
+            Forall(inner) => convert_arg(env, arg, inner, args),
+            IntroOuter(inner) => convert_arg(env, arg, inner, args),
             Ref(Reference::Builtin(name)) => match name.as_str() {
                 "Nat" => match arg.as_f64() {
                     None => Err(format!("Expected an unsigned int, got {:?}", arg)),
@@ -103,6 +117,35 @@ where
                     None => Err(format!("Expected a string, got {:?}", arg)),
                     Some(n) => Ok(Value::Char(n.chars().next().unwrap())),
                 },
+                "Boolean" => match arg.as_bool() {
+                    None => Err(format!("Expected a boolean, got {:?}", arg)),
+                    Some(b) => Ok(Value::Boolean(b)),
+                },
+                "Bytes" => match arg.as_list() {
+                    None => Err(format!("Expected a list of bytes, got {:?}", arg)),
+                    Some(items) => items
+                        .into_iter()
+                        .map(|item| match item.as_f64() {
+                            Some(n) if n >= 0.0 && n < 256.0 && n.fract() == 0.0 => Ok(n as u8),
+                            _ => Err(format!("Expected a byte, got {:?}", item)),
+                        })
+                        .collect::<Result<im::Vector<u8>, String>>()
+                        .map(Value::Bytes),
+                },
+                "Sequence" => match (args.as_slice(), arg.as_list()) {
+                    ([targ], Some(items)) => items
+                        .into_iter()
+                        .enumerate()
+                        .map(|(i, item)| {
+                            convert_arg(env, item, targ, vec![])
+                                .map(std::sync::Arc::new)
+                                .map_err(|err| format!("item {}: {}", i, err))
+                        })
+                        .collect::<Result<im::Vector<_>, String>>()
+                        .map(Value::Sequence),
+                    ([_], None) => Err(format!("Expected a list, got {:?}", arg)),
+                    _ => Err(format!("List type can only have one argument")),
+                },
                 _ => Err(format!("Unsupported builtin {}", name)),
             },
             Ref(Reference::DerivedId(id)) => {
@@ -123,13 +166,58 @@ where
                                     im::Vector::new(),
                                 ))
                             } else {
-                                convert_arg(arg, targ, vec![])
+                                Ok(Value::PartialConstructor(
+                                    Reference::DerivedId(id.clone()),
+                                    1,
+                                    im::Vector::from(vec![std::sync::Arc::new(convert_arg(
+                                        env,
+                                        arg,
+                                        targ,
+                                        vec![],
+                                    )?)]),
+                                ))
                             }
                         }
                         _ => Err(format!("Option type can only have one argument")),
                     }
+                } else if hash_raw == TUPLE_HASH {
+                    let typs = match args.as_slice() {
+                        [head, tail] => {
+                            let mut typs = vec![*head];
+                            typs.extend(
+                                tuple_items(tail)
+                                    .ok_or_else(|| format!("Invalid tuple type {:?}", tail))?,
+                            );
+                            typs
+                        }
+                        _ => return Err(format!("Tuple type must have two arguments")),
+                    };
+                    let items = match arg.as_list() {
+                        Some(items) if items.len() == typs.len() => items,
+                        _ => {
+                            return Err(format!(
+                                "Expected a list of {} items, got {:?}",
+                                typs.len(),
+                                arg
+                            ))
+                        }
+                    };
+                    let mut res = crate::unit();
+                    for (i, (item, typ)) in items.into_iter().zip(typs).enumerate().rev() {
+                        let item = convert_arg(env, item, typ, vec![])
+                            .map_err(|err| format!("item {}: {}", i, err))?;
+                        res = Value::PartialConstructor(
+                            Reference::DerivedId(id.clone()),
+                            0,
+                            im::Vector::from(vec![
+                                std::sync::Arc::new(item),
+                                std::sync::Arc::new(res),
+                            ]),
+                        );
+                    }
+                    Ok(res)
                 } else {
-                    Err(format!("Custom types not yet supported: {:?}", id))
+                    convert_data(env, arg, id, &args)
                 }
             }
         },
@@ -137,7 +225,110 @@ where
     }
 }
 
-pub fn convert_args<T>(args: Vec<T>, typs: &Vec<ABT<Type>>) -> Result<Vec<Value>, String>
+// The item types of a tuple type, which is nested like `(a, (b, ()))`.
+fn tuple_items(typ: &ABT<Type>) -> Option<Vec<&ABT<Type>>> {
+    match typ {
+        ABT::Tm(Type::Ref(Reference::DerivedId(id))) if id.hash.0 == UNIT_HASH => Some(vec![]),
+        ABT::Tm(Type::App(inner, tail)) => match &**inner {
+            ABT::Tm(Type::App(tuple, head)) => match &**tuple {
+                ABT::Tm(Type::Ref(Reference::DerivedId(id))) if id.hash.0 == TUPLE_HASH => {
+                    let mut items = vec![&**head];
+                    items.extend(tuple_items(tail)?);
+                    Some(items)
+                }
+                _ => None,
+            },
+            _ => None,
+        },
+        ABT::Tm(Type::Ann(inner, _)) => tuple_items(inner),
+        _ => None,
+    }
+}
+
+// A user-defined data type, using its declaration from the env.
+fn convert_data<T>(env: &RuntimeEnv, arg: T, id: &Id, args: &[&ABT<Type>]) -> Result<Value, String>
+where
+    T: std::fmt::Debug + Sized + ConvertibleArg<T>,
+{
+    let decl = match env.types.get(id) {
+        Some(TypeDecl::Data(decl)) => decl,
+        Some(TypeDecl::Effect(_)) => {
+            return Err(format!("{:?} is an ability, not a data type", id))
+        }
+        None => return Err(format!("Unknown type {:?}", id)),
+    };
+    let find = |name: &str| {
+        let suffix = format!(".{}", name);
+        decl.constructors
+            .iter()
+            .position(|(sym, _)| sym.text == name || sym.text.ends_with(&suffix))
+    };
+    let (number, fields) = match (arg.as_string(), arg.as_list()) {
+        (Some(name), _) => match find(&name) {
+            Some(number) => (number, vec![]),
+            None => return Err(format!("Unknown constructor {} for {:?}", name, id)),
+        },
+        (None, Some(mut items)) => {
+            let tag = items.first().and_then(|first| first.as_string());
+            match tag.as_ref().and_then(|name| find(name)) {
+                Some(number) => {
+                    items.remove(0);
+                    (number, items)
+                }
+                None if decl.constructors.len() == 1 => (0, items),
+                None => {
+                    return Err(format!(
+                        "Expected a constructor name for {:?}, got {:?}",
+                        id, tag
+                    ))
+                }
+            }
+        }
+        (None, None) => {
+            return Err(format!(
+                "Expected a constructor name or list, got {:?}",
+                arg
+            ))
+        }
+    };
+    let (name, constructor_type) = &decl.constructors[number];
+    let mut bindings = crate::check::Bindings::new();
+    for (sym, targ) in decl.bound.iter().zip(args) {
+        bindings.insert(sym.text.clone(), (*targ).clone());
+    }
+    let (field_types, _, _) =
+        crate::ir_runtime::extract_args(crate::check::strip_binders(constructor_type));
+    if fields.len() != field_types.len() {
+        return Err(format!(
+            "{} takes {} fields, got {}",
+            name.text,
+            field_types.len(),
+            fields.len()
+        ));
+    }
+    if field_types.is_empty() {
+        return Ok(Value::Constructor(Reference::DerivedId(id.clone()), number));
+    }
+    let mut values = im::Vector::new();
+    for (i, (field, typ)) in fields.into_iter().zip(field_types).enumerate() {
+        let typ = crate::check::substitute(&bindings, &typ);
+        values.push_back(std::sync::Arc::new(
+            convert_arg(env, field, &typ, vec![])
+                .map_err(|err| format!("{} field {}: {}", name.text, i, err))?,
+        ));
+    }
+    Ok(Value::PartialConstructor(
+        Reference::DerivedId(id.clone()),
+        number,
+        values,
+    ))
+}
+
+pub fn convert_args<T>(
+    env: &RuntimeEnv,
+    args: Vec<T>,
+    typs: &Vec<ABT<Type>>,
+) -> Result<Vec<Value>, String>
 where
     T: std::fmt::Debug + Sized + ConvertibleArg<T>,
 {
@@ -151,9 +342,125 @@ where
     let mut res = vec![];
     for (i, arg) in args.into_iter().enumerate() {
         res.push(
-            convert_arg(arg, &typs[i], vec![])
+            convert_arg(env, arg, &typs[i], vec![])
                 .map_err(|v| format!("Unable to convert argument {}: {}", i, v))?,
         );
     }
     Ok(res)
 }
+
+// Decoding unison values into rust ones, for the arguments of host functions
+// that handle ability requests (see `crate::handlers`).
+pub trait FromValue: Sized {
+    fn from_value(value: &Value) -> Result<Self, String>;
+}
+
+// And the other direction, for their return values.
+pub trait ToValue {
+    fn to_value(self) -> Value;
+}
+
+macro_rules! primitive {
+    ($rust:ty, $variant:ident, $name:expr) => {
+        impl FromValue for $rust {
+            fn from_value(value: &Value) -> Result<Self, String> {
+                match value {
+                    Value::$variant(inner) => Ok(inner.clone()),
+                    _ => Err(format!("Expected {}, got {:?}", $name, value)),
+                }
+            }
+        }
+
+        impl ToValue for $rust {
+            fn to_value(self) -> Value {
+                Value::$variant(self)
+            }
+        }
+    };
+}
+
+primitive!(u64, Nat, "a Nat");
+primitive!(i64, Int, "an Int");
+primitive!(f64, Float, "a Float");
+primitive!(bool, Boolean, "a Boolean");
+primitive!(String, Text, "a Text");
+primitive!(char, Char, "a Char");
+
+impl FromValue for Value {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        Ok(value.clone())
+    }
+}
+
+impl ToValue for Value {
+    fn to_value(self) -> Value {
+        self
+    }
+}
+
+impl FromValue for () {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        match value {
+            Value::Constructor(Reference::DerivedId(id), 0) if id.hash.0 == UNIT_HASH => Ok(()),
+            _ => Err(format!("Expected unit, got {:?}", value)),
+        }
+    }
+}
+
+impl ToValue for () {
+    fn to_value(self) -> Value {
+        crate::unit()
+    }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        match value {
+            Value::Sequence(items) => items.iter().map(|item| T::from_value(item)).collect(),
+            _ => Err(format!("Expected a list, got {:?}", value)),
+        }
+    }
+}
+
+impl<T: ToValue> ToValue for Vec<T> {
+    fn to_value(self) -> Value {
+        Value::Sequence(
+            self.into_iter()
+                .map(|item| std::sync::Arc::new(item.to_value()))
+                .collect(),
+        )
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        match value {
+            Value::Constructor(Reference::DerivedId(id), 0) if id.hash.0 == OPTION_HASH => Ok(None),
+            Value::PartialConstructor(Reference::DerivedId(id), 0, args)
+                if id.hash.0 == OPTION_HASH && args.is_empty() =>
+            {
+                Ok(None)
+            }
+            Value::PartialConstructor(Reference::DerivedId(id), 1, args)
+                if id.hash.0 == OPTION_HASH && args.len() == 1 =>
+            {
+                T::from_value(&args[0]).map(Some)
+            }
+            _ => Err(format!("Expected an Optional, got {:?}", value)),
+        }
+    }
+}
+
+impl<T: ToValue> ToValue for Option<T> {
+    fn to_value(self) -> Value {
+        let option = Reference::from_hash(OPTION_HASH);
+        match self {
+            None => Value::Constructor(option, 0),
+            Some(value) => Value::PartialConstructor(
+                option,
+                1,
+                im::Vector::from(vec![std::sync::Arc::new(value.to_value())]),
+            ),
+        }
+    }
+}
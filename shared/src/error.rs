@@ -0,0 +1,141 @@
+use super::frame::{Frame, Source};
+use super::stack_trace::StackTrace;
+use super::types::*;
+use serde_derive::{Deserialize, Serialize};
+
+// Anything that can go wrong while evaluating IR. These used to be
+// `unreachable!`s, which would take down the whole host process.
+// Values & patterns are boxed, to keep the `Result`s that carry these small.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum RuntimeError {
+    EmptyStack,
+    UnboundVariable(Symbol),
+    // (what we expected, what we got)
+    UnexpectedValue(String, Box<Value>),
+    NotCallable(Box<Value>),
+    InvalidBuiltinArgs(String, Vec<Value>),
+    UnknownBuiltin(String),
+    PatternMatchFailure(Box<Value>),
+    // Universal equality & comparison aren't defined for functions
+    Incomparable(Box<Value>, Box<Value>),
+    UnsupportedPattern(Box<Pattern>),
+    // An ability was requested, but nobody handled it & it's not in the effects map
+    MissingEffect(Reference),
+    // A builtin returns this type, but the program doesn't have it
+    MissingType(String),
+    UnknownAbility(Reference, usize),
+    // The FFI returned a value that doesn't match the ability's return type
+    // (ability, constructor number, value, what's wrong with it)
+    InvalidFFI(Reference, usize, Box<Value>, Vec<String>),
+    // An async FFI handler failed, with this message
+    AsyncFailure(Reference, usize, String),
+    // A host's handler couldn't use the request's arguments, for this reason
+    InvalidRequest(Reference, usize, String),
+    UnknownTerm(Id),
+    // Every thread is waiting on another one (see `concurrent`)
+    Deadlock,
+    DivideByZero,
+    // The State's budget was used up after this many steps
+    OutOfFuel(usize),
+    // The FFI's policy refused a request
+    PolicyViolation(crate::policy::Violation),
+    // The program called `bug` or `todo`
+    Bug(Box<Value>),
+    Todo(Box<Value>),
+    // The program asked to stop, with this exit code. It's up to the host what to do.
+    Exit(i32),
+    Internal(String),
+}
+
+impl std::fmt::Display for RuntimeError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        use RuntimeError::*;
+        match self {
+            EmptyStack => write!(fmt, "Tried to pop from an empty stack"),
+            UnboundVariable(sym) => write!(fmt, "Variable {:?} not found", sym),
+            UnexpectedValue(expected, value) => {
+                write!(fmt, "Expected {}, found {:?}", expected, value)
+            }
+            NotCallable(value) => write!(fmt, "Value is not callable: {:?}", value),
+            InvalidBuiltinArgs(name, args) => {
+                write!(fmt, "Invalid arguments for builtin {}: {:?}", name, args)
+            }
+            UnknownBuiltin(name) => write!(fmt, "Unknown builtin {}", name),
+            PatternMatchFailure(value) => write!(fmt, "Pattern match failure! {:?}", value),
+            Incomparable(one, two) => write!(fmt, "Cannot compare {:?} with {:?}", one, two),
+            UnsupportedPattern(pattern) => write!(fmt, "Unsupported pattern: {:?}", pattern),
+            MissingEffect(kind) => write!(fmt, "No handler or effect found for {:?}", kind),
+            MissingType(name) => write!(fmt, "The type {} isn't in the environment", name),
+            UnknownAbility(kind, number) => {
+                write!(fmt, "Unknown ability constructor {:?} # {}", kind, number)
+            }
+            InvalidFFI(kind, number, value, errors) => write!(
+                fmt,
+                "Invalid FFI response for {:?} # {}: {:?}\n{}",
+                kind,
+                number,
+                value,
+                errors.join("\n")
+            ),
+            AsyncFailure(kind, number, message) => write!(
+                fmt,
+                "Async FFI handler for {:?} # {} failed: {}",
+                kind, number, message
+            ),
+            InvalidRequest(kind, number, message) => {
+                write!(fmt, "Bad request for {:?} # {}: {}", kind, number, message)
+            }
+            UnknownTerm(id) => write!(fmt, "Term not found: {:?}", id),
+            Deadlock => write!(fmt, "Every thread is blocked, waiting on another one"),
+            DivideByZero => write!(fmt, "Divide by zero"),
+            OutOfFuel(steps) => write!(fmt, "Ran out of fuel after {} steps", steps),
+            PolicyViolation(violation) => write!(fmt, "Policy violation: {}", violation),
+            Bug(value) => write!(fmt, "bug: {:?}", value),
+            Todo(value) => write!(fmt, "todo: {:?}", value),
+            Exit(code) => write!(fmt, "Exited with code {}", code),
+            Internal(message) => write!(fmt, "Internal error: {}", message),
+        }
+    }
+}
+
+// A RuntimeError, along with where it happened.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Failure {
+    pub error: Box<RuntimeError>,
+    // the source of the topmost frame when things went wrong, if there was one
+    pub source: Option<Source>,
+    // the index of the failing instruction within that source
+    pub idx: usize,
+    pub trace: StackTrace,
+}
+
+impl Failure {
+    // `frames` is the stack at the time of failure, topmost frame first.
+    pub fn new(error: RuntimeError, frames: &[Frame], idx: usize) -> Self {
+        Failure {
+            error: Box::new(error),
+            source: frames.first().map(|frame| frame.source.clone()),
+            idx,
+            trace: StackTrace::from_frames(frames, idx),
+        }
+    }
+
+    // The error message followed by the stack trace, with terms named by `name`.
+    pub fn render<F: Fn(&Id) -> Option<String>>(&self, name: F) -> String {
+        format!("{}\n{}", self.error, self.trace.render(name))
+    }
+
+    // Running out of fuel leaves the State untouched, so you can `refuel` and keep going.
+    pub fn is_resumable(&self) -> bool {
+        match *self.error {
+            RuntimeError::OutOfFuel(_) => true,
+            _ => false,
+        }
+    }
+}
+
+impl std::fmt::Display for Failure {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "{}\n{}", self.error, self.trace)
+    }
+}
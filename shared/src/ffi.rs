@@ -7,14 +7,26 @@ pub trait FFI {
     // TODO maybe the State should have knowledge of the types of all `abilities` that it
     // might come across?
     // If this returns `None`, that means that the request couldn't be handled synchronously,
-    // e.g. we need to just bail straight out.
+    // e.g. we need to just bail straight out. An error (e.g. for arguments the handler
+    // can't use) stops the run, with the error as the failure.
     fn handle_request_sync(
         &mut self,
         typ: &ABT<Type>,
         kind: &Reference,
         number: usize,
         args: &Vec<Arc<Value>>,
-    ) -> Option<Value>;
+    ) -> Result<Option<Value>, crate::error::RuntimeError>;
+
+    // Called before each request is handled (sync or not). Returning an error
+    // stops the run, with the error as the failure.
+    fn check_request(
+        &mut self,
+        _kind: &Reference,
+        _number: usize,
+        _args: &Vec<Arc<Value>>,
+    ) -> Result<(), crate::error::RuntimeError> {
+        Ok(())
+    }
 
     // This is used at the top level, once we've bailed.
     fn handle_request(&mut self, request: crate::state::FullRequest);
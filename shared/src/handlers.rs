@@ -0,0 +1,193 @@
+// A registry of rust functions that handle ability requests, so that hosts can
+// provide their own abilities without matching on hashes.
+//
+// Handlers are bound to an ability, by its fully-qualified name or its full hash,
+// and a constructor index. Arguments are decoded with `convert::FromValue`, and
+// the result encoded with `convert::ToValue`, so a handler for
+// `Wait.waitMs : Nat ->{Wait} ()` can be `|millis: u64| sleep(millis)`.
+//
+// Handlers added with `add_future` return a future instead, and are only
+// handled by an `async_ffi::AsyncFFI`.
+use super::async_ffi::ValueFuture;
+use super::convert::{FromValue, ToValue};
+use super::types::*;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+
+// Gets the request's arguments, and returns the value to resume with.
+pub type Handler = Box<dyn FnMut(&[Arc<Value>]) -> Result<Value, String>>;
+
+// Gets the request's arguments, and returns a future for the value to resume with.
+pub type FutureHandler = Box<dyn FnMut(&[Arc<Value>]) -> ValueFuture>;
+
+// Implemented for functions of up to four `FromValue` arguments that return
+// something `ToValue`.
+pub trait IntoHandler<Args> {
+    fn into_handler(self) -> Handler;
+}
+
+// The same, for functions that return a future of something `ToValue`.
+pub trait IntoFutureHandler<Args> {
+    fn into_future_handler(self) -> FutureHandler;
+}
+
+macro_rules! into_handler {
+    ($($arg:ident),*) => {
+        impl<F, R, $($arg),*> IntoHandler<($($arg,)*)> for F
+        where
+            F: FnMut($($arg),*) -> R + 'static,
+            R: ToValue,
+            $($arg: FromValue),*
+        {
+            #[allow(non_snake_case)]
+            fn into_handler(mut self) -> Handler {
+                Box::new(move |args| match args {
+                    [$($arg),*] => Ok(self($($arg::from_value($arg)?),*).to_value()),
+                    _ => {
+                        let expected: &[&str] = &[$(stringify!($arg)),*];
+                        Err(format!(
+                            "Expected {} arguments, got {}",
+                            expected.len(),
+                            args.len()
+                        ))
+                    }
+                })
+            }
+        }
+
+        impl<F, Fut, R, $($arg),*> IntoFutureHandler<($($arg,)*)> for F
+        where
+            F: FnMut($($arg),*) -> Fut + 'static,
+            Fut: Future<Output = R> + 'static,
+            R: ToValue,
+            $($arg: FromValue),*
+        {
+            #[allow(non_snake_case)]
+            fn into_future_handler(mut self) -> FutureHandler {
+                let mut handler = move |args: &[Arc<Value>]| match args {
+                    [$($arg),*] => Ok(self($($arg::from_value($arg)?),*)),
+                    _ => {
+                        let expected: &[&str] = &[$(stringify!($arg)),*];
+                        Err(format!(
+                            "Expected {} arguments, got {}",
+                            expected.len(),
+                            args.len()
+                        ))
+                    }
+                };
+                Box::new(move |args| match handler(args) {
+                    Ok(future) => Box::pin(async move { Ok(future.await.to_value()) }),
+                    Err(message) => Box::pin(async move { Err(message) }),
+                })
+            }
+        }
+    };
+}
+
+into_handler!();
+into_handler!(A);
+into_handler!(A, B);
+into_handler!(A, B, C);
+into_handler!(A, B, C, D);
+
+#[derive(Default)]
+pub struct Handlers {
+    // fully-qualified name -> reference, for the abilities in the codebase
+    abilities: HashMap<String, Reference>,
+    handlers: HashMap<(Reference, usize), Handler>,
+    futures: HashMap<(Reference, usize), FutureHandler>,
+}
+
+impl Handlers {
+    pub fn new(abilities: HashMap<String, Reference>) -> Self {
+        Handlers {
+            abilities,
+            handlers: HashMap::new(),
+            futures: HashMap::new(),
+        }
+    }
+
+    // `ability` is either a name like `base.io.Console` (the leading `.` is optional),
+    // or `#` followed by the ability's full hash.
+    pub fn resolve(&self, ability: &str) -> Option<Reference> {
+        if ability.starts_with('#') {
+            return Some(Reference::from_hash(&ability[1..]));
+        }
+        let name = ability.trim_start_matches('.');
+        self.abilities.get(name).cloned()
+    }
+
+    pub fn add<Args, F: IntoHandler<Args>>(
+        &mut self,
+        ability: &str,
+        number: usize,
+        handler: F,
+    ) -> Result<(), String> {
+        let kind = self
+            .resolve(ability)
+            .ok_or_else(|| format!("Ability {} not found", ability))?;
+        self.add_raw(kind, number, handler.into_handler());
+        Ok(())
+    }
+
+    pub fn add_future<Args, F: IntoFutureHandler<Args>>(
+        &mut self,
+        ability: &str,
+        number: usize,
+        handler: F,
+    ) -> Result<(), String> {
+        let kind = self
+            .resolve(ability)
+            .ok_or_else(|| format!("Ability {} not found", ability))?;
+        self.add_future_raw(kind, number, handler.into_future_handler());
+        Ok(())
+    }
+
+    // Replaces any existing handler for the constructor.
+    pub fn add_raw(&mut self, kind: Reference, number: usize, handler: Handler) {
+        self.futures.remove(&(kind.clone(), number));
+        self.handlers.insert((kind, number), handler);
+    }
+
+    // Replaces any existing handler for the constructor.
+    pub fn add_future_raw(&mut self, kind: Reference, number: usize, handler: FutureHandler) {
+        self.handlers.remove(&(kind.clone(), number));
+        self.futures.insert((kind, number), handler);
+    }
+
+    pub fn handles(&self, kind: &Reference) -> bool {
+        self.handlers
+            .keys()
+            .chain(self.futures.keys())
+            .any(|(reference, _)| reference == kind)
+    }
+
+    pub fn handles_future(&self, kind: &Reference, number: usize) -> bool {
+        self.futures.contains_key(&(kind.clone(), number))
+    }
+
+    // `None` if there's no handler for the constructor, otherwise the result
+    // (or the reason the arguments couldn't be decoded).
+    pub fn handle(
+        &mut self,
+        kind: &Reference,
+        number: usize,
+        args: &[Arc<Value>],
+    ) -> Option<Result<Value, String>> {
+        self.handlers
+            .get_mut(&(kind.clone(), number))
+            .map(|handler| handler(args))
+    }
+
+    pub fn handle_future(
+        &mut self,
+        kind: &Reference,
+        number: usize,
+        args: &[Arc<Value>],
+    ) -> Option<ValueFuture> {
+        self.futures
+            .get_mut(&(kind.clone(), number))
+            .map(|handler| handler(args))
+    }
+}
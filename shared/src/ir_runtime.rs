@@ -1,3 +1,4 @@
+use super::error::RuntimeError;
 use super::types::*;
 use super::types::{RuntimeEnv, IR};
 use log::info;
@@ -88,6 +89,8 @@ pub fn extract_args(
             Ann(t, _) => extract_args(t),
             t => (vec![], Default::default(), ABT::Tm(t.clone())),
         },
+        // A polymorphic result, like `take : MVar a ->{e} a`
+        ABT::Var(..) => (vec![], Default::default(), typ.clone()),
         _ => unreachable!("Um not a Tm {:?}", typ),
     }
 }
@@ -99,7 +102,7 @@ pub fn eval<T: crate::ffi::FFI>(
     trace: &mut Traces,
     do_trace: bool,
     effects: std::collections::HashMap<String, ABT<Type>>,
-) -> Result<Option<Arc<Value>>, crate::state::InvalidFFI> {
+) -> Result<Option<Arc<Value>>, crate::error::Failure> {
     let mut state = crate::state::State::new_value(&env, Id::from_string(hash), do_trace, effects);
     state.run_to_end(ffi, trace)
 }
@@ -123,22 +126,32 @@ impl RuntimeEnv {
             // };
         }
         let hash = Id::from_string("<eval>");
+        // It's a different term each time
+        self.memo.remove(&hash);
         self.terms.insert(hash.clone(), (cmds, typ));
         Ok(hash)
     }
 
-    pub fn get_ability_type(&self, kind: &Reference, number: usize) -> ABT<Type> {
+    pub fn get_ability_type(
+        &self,
+        kind: &Reference,
+        number: usize,
+    ) -> Result<ABT<Type>, RuntimeError> {
         let decl = match kind {
-            Reference::DerivedId(id) => self.types.get(&id).expect("Ability type not found"),
-            _ => unreachable!("No builtin abilities"),
+            Reference::DerivedId(id) => self.types.get(&id),
+            // No builtin abilities
+            _ => None,
         };
         let data = match decl {
-            TypeDecl::Effect(data) => data,
-            _ => unreachable!("Not an effect type"),
+            Some(TypeDecl::Effect(data)) => data,
+            _ => return Err(RuntimeError::UnknownAbility(kind.clone(), number)),
+        };
+        let (_, constructor_type) = match data.constructors.get(number) {
+            Some(constructor) => constructor,
+            None => return Err(RuntimeError::UnknownAbility(kind.clone(), number)),
         };
-        let (_, constructor_type) = &data.constructors[number];
         // println!("Extracting args: {:?}", constructor_type);
-        constructor_type.clone()
+        Ok(constructor_type.clone())
         // let (_arg_types, _effects, return_type) = extract_args(constructor_type);
         // return_type
     }
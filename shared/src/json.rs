@@ -0,0 +1,603 @@
+// Converting unison values to & from JSON, directed by their types, for talking
+// to other services.
+//
+// - `Nat`, `Int` & `Float` are numbers, `Boolean`s are booleans, `Text` is a string,
+//   and a `Char` is a string of one character
+// - `Bytes` are a base64 string (an array of numbers is accepted too)
+// - lists & tuples are arrays
+// - `()` is null, and an `Optional a` is null or the `a`. If the `a` can be null
+//   itself (e.g. `Optional (Optional Nat)`), `Some` is wrapped in an array.
+// - a record (a type with one constructor, whose field names are in `fields`) is an
+//   object. A type with one constructor that we don't have field names for is an
+//   array of its fields.
+// - otherwise, a constructor without fields is its name, and one with fields is
+//   an object like `{"Node": [left, value, right]}`. Names are the last segment of
+//   the constructor's name, though fully-qualified names are accepted when decoding.
+//
+// A type variable that isn't bound to anything (e.g. the result of a polymorphic
+// function) is encoded based on the value alone, but can't be decoded.
+use crate::check::{strip_binders, substitute, unapply, Bindings};
+use crate::convert::{OPTION_HASH, TUPLE_HASH, UNIT_HASH};
+use crate::types::*;
+use serde_json::Value as Json;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub struct Codec<'a> {
+    pub env: &'a RuntimeEnv,
+    // record type -> the names of its fields, in order
+    pub fields: HashMap<Reference, Vec<String>>,
+}
+
+// What a type boils down to, once variables are resolved.
+enum Shape {
+    // a builtin type, and its arguments
+    Builtin(String, Vec<ABT<Type>>),
+    // a data type, and its arguments
+    Data(Reference, Vec<ABT<Type>>),
+    Function,
+    Effects,
+    // an unbound type variable
+    Any,
+}
+
+fn shape(bindings: &Bindings, typ: &ABT<Type>) -> Shape {
+    use ABT::*;
+    match typ {
+        // Bound types have already been substituted, so they don't refer to `bindings`
+        Var(sym, _) => match bindings.get(&sym.text) {
+            Some(bound) => shape(&Bindings::new(), bound),
+            None => Shape::Any,
+        },
+        Cycle(inner) => shape(bindings, inner),
+        Abs(sym, _, inner) => shape(&bindings.without(&sym.text), inner),
+        Tm(Type::Ann(inner, _))
+        | Tm(Type::Effect(_, inner))
+        | Tm(Type::Forall(inner))
+        | Tm(Type::IntroOuter(inner)) => shape(bindings, inner),
+        Tm(Type::Arrow(_, _)) => Shape::Function,
+        Tm(Type::Effects(_)) => Shape::Effects,
+        Tm(typ) => {
+            let (head, args) = unapply(typ);
+            let args: Vec<ABT<Type>> = args.iter().map(|arg| substitute(bindings, arg)).collect();
+            match head {
+                Tm(Type::Ref(Reference::Builtin(name))) => Shape::Builtin(name, args),
+                Tm(Type::Ref(reference)) => Shape::Data(reference, args),
+                Var(sym, _) => match bindings.get(&sym.text) {
+                    Some(bound) => shape(&Bindings::new(), &apply(bound.clone(), args)),
+                    None => Shape::Any,
+                },
+                // A type-level lambda, applied
+                Abs(sym, _, inner) if !args.is_empty() => {
+                    let mut args = args.into_iter();
+                    let bindings = bindings.update(sym.text.clone(), args.next().unwrap());
+                    shape(&bindings, &apply(*inner, args.collect()))
+                }
+                Tm(Type::Forall(inner)) | Tm(Type::IntroOuter(inner)) | Cycle(inner) => {
+                    shape(bindings, &apply(*inner, args))
+                }
+                _ => Shape::Any,
+            }
+        }
+    }
+}
+
+fn apply(typ: ABT<Type>, args: Vec<ABT<Type>>) -> ABT<Type> {
+    args.into_iter().fold(typ, |typ, arg| {
+        ABT::Tm(Type::App(Box::new(typ), Box::new(arg)))
+    })
+}
+
+// An unbound type variable, for when we don't know a type's arguments.
+fn any() -> ABT<Type> {
+    ABT::Var(
+        Symbol {
+            num: 0,
+            text: "_".to_owned(),
+            unique: 0,
+        },
+        0,
+    )
+}
+
+fn is_hash(reference: &Reference, hash: &str) -> bool {
+    match reference {
+        Reference::DerivedId(id) => id.hash.0 == hash,
+        Reference::Builtin(_) => false,
+    }
+}
+
+// Whether values of the type can be encoded as null
+fn nullable(typ: &ABT<Type>) -> bool {
+    match shape(&Bindings::new(), typ) {
+        Shape::Data(reference, _) => {
+            is_hash(&reference, UNIT_HASH) || is_hash(&reference, OPTION_HASH)
+        }
+        _ => false,
+    }
+}
+
+// `base.Optional.Some` -> `Some`
+fn short_name(sym: &Symbol) -> &str {
+    sym.text.rsplit('.').next().unwrap_or(&sym.text)
+}
+
+// The constructor number & its fields
+fn constructor(value: &Value) -> Option<(&Reference, usize, Vec<Arc<Value>>)> {
+    match value {
+        Value::Constructor(r, number) => Some((r, *number, vec![])),
+        Value::PartialConstructor(r, number, fields) => {
+            Some((r, *number, fields.iter().cloned().collect()))
+        }
+        _ => None,
+    }
+}
+
+impl<'a> Codec<'a> {
+    pub fn new(env: &'a RuntimeEnv) -> Self {
+        Codec {
+            env,
+            fields: HashMap::new(),
+        }
+    }
+
+    pub fn to_json(&self, typ: &ABT<Type>, value: &Value) -> Result<Json, String> {
+        self.encode(shape(&Bindings::new(), typ), value, "$")
+    }
+
+    pub fn from_json(&self, typ: &ABT<Type>, json: &Json) -> Result<Value, String> {
+        self.decode(shape(&Bindings::new(), typ), json, "$")
+    }
+
+    fn encode(&self, shape: Shape, value: &Value, path: &str) -> Result<Json, String> {
+        match (shape, value) {
+            (Shape::Builtin(name, args), value) => self.encode_builtin(&name, &args, value, path),
+            (Shape::Data(reference, args), value) => {
+                self.encode_data(&reference, &args, value, path)
+            }
+            (Shape::Function, _) => Err(format!("{}: Functions can't be encoded as JSON", path)),
+            (Shape::Effects, _) => Err(format!(
+                "{}: Expected a value, but the type is a set of effects",
+                path
+            )),
+            (Shape::Any, value) => match value {
+                Value::Int(_) => self.encode_builtin("Int", &[], value, path),
+                Value::Nat(_) => self.encode_builtin("Nat", &[], value, path),
+                Value::Float(_) => self.encode_builtin("Float", &[], value, path),
+                Value::Boolean(_) => self.encode_builtin("Boolean", &[], value, path),
+                Value::Text(_) => self.encode_builtin("Text", &[], value, path),
+                Value::Char(_) => self.encode_builtin("Char", &[], value, path),
+                Value::Bytes(_) => self.encode_builtin("Bytes", &[], value, path),
+                Value::Sequence(_) => self.encode_builtin("Sequence", &[any()], value, path),
+                Value::Constructor(r, _) | Value::PartialConstructor(r, _, _) => {
+                    self.encode_data(r, &[], value, path)
+                }
+                _ => Err(format!("{}: Can't encode {:?} as JSON", path, value)),
+            },
+        }
+    }
+
+    fn encode_builtin(
+        &self,
+        name: &str,
+        args: &[ABT<Type>],
+        value: &Value,
+        path: &str,
+    ) -> Result<Json, String> {
+        match (name, args, value) {
+            ("Int", [], Value::Int(i)) => Ok(Json::from(*i)),
+            ("Nat", [], Value::Nat(n)) => Ok(Json::from(*n)),
+            ("Float", [], Value::Float(f)) => serde_json::Number::from_f64(*f)
+                .map(Json::Number)
+                .ok_or_else(|| format!("{}: {} can't be represented in JSON", path, f)),
+            ("Boolean", [], Value::Boolean(b)) => Ok(Json::Bool(*b)),
+            ("Text", [], Value::Text(t)) => Ok(Json::String(t.clone())),
+            ("Char", [], Value::Char(c)) => Ok(Json::String(c.to_string())),
+            ("Bytes", [], Value::Bytes(bytes)) => Ok(Json::String(base64::encode(
+                &bytes.iter().cloned().collect::<Vec<u8>>(),
+            ))),
+            ("Sequence", [item], Value::Sequence(items)) => items
+                .iter()
+                .enumerate()
+                .map(|(i, value)| {
+                    self.encode(
+                        shape(&Bindings::new(), item),
+                        value,
+                        &format!("{}[{}]", path, i),
+                    )
+                })
+                .collect::<Result<Vec<Json>, String>>()
+                .map(Json::Array),
+            _ => Err(format!("{}: Expected {}, found {:?}", path, name, value)),
+        }
+    }
+
+    fn encode_data(
+        &self,
+        reference: &Reference,
+        args: &[ABT<Type>],
+        value: &Value,
+        path: &str,
+    ) -> Result<Json, String> {
+        let (r, number, fields) = match constructor(value) {
+            Some((r, number, fields)) if r == reference => (r, number, fields),
+            _ => {
+                return Err(format!(
+                    "{}: Expected a value of type {:?}, found {:?}",
+                    path, reference, value
+                ))
+            }
+        };
+        let arg = |i: usize| args.get(i).cloned().unwrap_or_else(any);
+        if is_hash(r, UNIT_HASH) {
+            return Ok(Json::Null);
+        }
+        if is_hash(r, OPTION_HASH) {
+            return match fields.as_slice() {
+                [] => Ok(Json::Null),
+                [inner] => {
+                    let json = self.encode(
+                        shape(&Bindings::new(), &arg(0)),
+                        inner,
+                        &format!("{}.Some", path),
+                    )?;
+                    Ok(if nullable(&arg(0)) {
+                        Json::Array(vec![json])
+                    } else {
+                        json
+                    })
+                }
+                _ => Err(format!("{}: Invalid Optional {:?}", path, value)),
+            };
+        }
+        if is_hash(r, TUPLE_HASH) {
+            let mut items = vec![];
+            let (mut value, mut args) = (value.clone(), args.to_vec());
+            loop {
+                match constructor(&value) {
+                    Some((r, 0, fields)) if is_hash(r, TUPLE_HASH) && fields.len() == 2 => {
+                        let head = args.get(0).cloned().unwrap_or_else(any);
+                        items.push(self.encode(
+                            shape(&Bindings::new(), &head),
+                            &fields[0],
+                            &format!("{}[{}]", path, items.len()),
+                        )?);
+                        args = match shape(
+                            &Bindings::new(),
+                            &args.get(1).cloned().unwrap_or_else(any),
+                        ) {
+                            Shape::Data(_, args) => args,
+                            _ => vec![],
+                        };
+                        value = (*fields[1]).clone();
+                    }
+                    Some((r, 0, _)) if is_hash(r, UNIT_HASH) => return Ok(Json::Array(items)),
+                    _ => return Err(format!("{}: Invalid tuple {:?}", path, value)),
+                }
+            }
+        }
+        let decl = self.data_decl(r, path)?;
+        let (name, field_types) = self.field_types(decl, args, number, path)?;
+        if fields.len() != field_types.len() {
+            return Err(format!(
+                "{}: Expected {} to have {} fields, found {}",
+                path,
+                name.text,
+                field_types.len(),
+                fields.len()
+            ));
+        }
+        let record = self.record_fields(r, decl, field_types.len());
+        let mut values = vec![];
+        for (i, (typ, field)) in field_types.iter().zip(fields.iter()).enumerate() {
+            let path = match record {
+                Some(names) => format!("{}.{}", path, names[i]),
+                None => format!("{}.{}.{}", path, short_name(name), i),
+            };
+            values.push(self.encode(shape(&Bindings::new(), typ), field, &path)?);
+        }
+        Ok(match record {
+            Some(names) => Json::Object(names.iter().cloned().zip(values).collect()),
+            None if decl.constructors.len() == 1 && !values.is_empty() => Json::Array(values),
+            None if values.is_empty() => Json::String(short_name(name).to_owned()),
+            None => {
+                let mut object = serde_json::Map::new();
+                object.insert(short_name(name).to_owned(), Json::Array(values));
+                Json::Object(object)
+            }
+        })
+    }
+
+    fn data_decl(&self, reference: &Reference, path: &str) -> Result<&'a DataDecl, String> {
+        match reference {
+            Reference::DerivedId(id) => match self.env.types.get(id) {
+                Some(TypeDecl::Data(decl)) => Ok(decl),
+                Some(TypeDecl::Effect(_)) => Err(format!(
+                    "{}: Expected a value, but {:?} is an ability",
+                    path, reference
+                )),
+                None => Err(format!("{}: Unknown type {:?}", path, reference)),
+            },
+            Reference::Builtin(name) => Err(format!("{}: Unknown builtin type {}", path, name)),
+        }
+    }
+
+    // The constructor's name, and the types of its fields.
+    fn field_types(
+        &self,
+        decl: &'a DataDecl,
+        args: &[ABT<Type>],
+        number: usize,
+        path: &str,
+    ) -> Result<(&'a Symbol, Vec<ABT<Type>>), String> {
+        let (name, constructor_type) = decl
+            .constructors
+            .get(number)
+            .ok_or_else(|| format!("{}: No constructor #{}", path, number))?;
+        let mut bindings = Bindings::new();
+        for (sym, arg) in decl.bound.iter().zip(args) {
+            bindings.insert(sym.text.clone(), arg.clone());
+        }
+        let (field_types, _, _) = crate::ir_runtime::extract_args(strip_binders(constructor_type));
+        Ok((
+            name,
+            field_types
+                .iter()
+                .map(|typ| substitute(&bindings, typ))
+                .collect(),
+        ))
+    }
+
+    // The field names, if the type is a record with `count` fields.
+    fn record_fields(
+        &self,
+        reference: &Reference,
+        decl: &DataDecl,
+        count: usize,
+    ) -> Option<&Vec<String>> {
+        match self.fields.get(reference) {
+            Some(names) if decl.constructors.len() == 1 && names.len() == count && count > 0 => {
+                Some(names)
+            }
+            _ => None,
+        }
+    }
+
+    fn decode(&self, shape: Shape, json: &Json, path: &str) -> Result<Value, String> {
+        match shape {
+            Shape::Builtin(name, args) => self.decode_builtin(&name, &args, json, path),
+            Shape::Data(reference, args) => self.decode_data(&reference, &args, json, path),
+            Shape::Function => Err(format!("{}: Functions can't be decoded from JSON", path)),
+            Shape::Effects => Err(format!(
+                "{}: Expected a value, but the type is a set of effects",
+                path
+            )),
+            Shape::Any => Err(format!(
+                "{}: Can't decode a value of a polymorphic type from JSON",
+                path
+            )),
+        }
+    }
+
+    fn decode_builtin(
+        &self,
+        name: &str,
+        args: &[ABT<Type>],
+        json: &Json,
+        path: &str,
+    ) -> Result<Value, String> {
+        let expected = |what: &str| Err(format!("{}: Expected {}, found {}", path, what, json));
+        match (name, args) {
+            ("Int", []) => json
+                .as_i64()
+                .map(Value::Int)
+                .map_or_else(|| expected("an Int"), Ok),
+            ("Nat", []) => json
+                .as_u64()
+                .map(Value::Nat)
+                .map_or_else(|| expected("a Nat"), Ok),
+            ("Float", []) => json
+                .as_f64()
+                .map(Value::Float)
+                .map_or_else(|| expected("a Float"), Ok),
+            ("Boolean", []) => json
+                .as_bool()
+                .map(Value::Boolean)
+                .map_or_else(|| expected("a Boolean"), Ok),
+            ("Text", []) => match json {
+                Json::String(text) => Ok(Value::Text(text.clone())),
+                _ => expected("a string"),
+            },
+            ("Char", []) => {
+                let mut chars = json.as_str().map(|text| text.chars());
+                match chars.as_mut().map(|chars| (chars.next(), chars.next())) {
+                    Some((Some(c), None)) => Ok(Value::Char(c)),
+                    _ => expected("a string of one character"),
+                }
+            }
+            ("Bytes", []) => match json {
+                Json::String(encoded) => base64::decode(encoded)
+                    .map(|bytes| Value::Bytes(bytes.into_iter().collect()))
+                    .map_err(|err| format!("{}: Invalid base64: {}", path, err)),
+                Json::Array(items) => items
+                    .iter()
+                    .map(|item| match item.as_u64() {
+                        Some(n) if n < 256 => Ok(n as u8),
+                        _ => Err(format!("{}: Expected a byte, found {}", path, item)),
+                    })
+                    .collect::<Result<im::Vector<u8>, String>>()
+                    .map(Value::Bytes),
+                _ => expected("a base64 string"),
+            },
+            ("Sequence", [item]) => match json {
+                Json::Array(items) => items
+                    .iter()
+                    .enumerate()
+                    .map(|(i, json)| {
+                        self.decode(
+                            shape(&Bindings::new(), item),
+                            json,
+                            &format!("{}[{}]", path, i),
+                        )
+                        .map(Arc::new)
+                    })
+                    .collect::<Result<im::Vector<_>, String>>()
+                    .map(Value::Sequence),
+                _ => expected("an array"),
+            },
+            _ => Err(format!("{}: Can't decode a {} from JSON", path, name)),
+        }
+    }
+
+    fn decode_data(
+        &self,
+        reference: &Reference,
+        args: &[ABT<Type>],
+        json: &Json,
+        path: &str,
+    ) -> Result<Value, String> {
+        let refs = &self.env.builtins.refs;
+        if is_hash(reference, UNIT_HASH) {
+            return match json {
+                Json::Null => Ok(crate::unit()),
+                _ => Err(format!("{}: Expected null, found {}", path, json)),
+            };
+        }
+        if is_hash(reference, OPTION_HASH) {
+            let inner = args
+                .get(0)
+                .ok_or_else(|| format!("{}: Optional needs a type argument", path))?;
+            let json = match json {
+                Json::Null => return Ok(refs.none()),
+                Json::Array(items) if nullable(inner) && items.len() == 1 => &items[0],
+                _ if nullable(inner) => {
+                    return Err(format!(
+                        "{}: Expected null or [value], found {}",
+                        path, json
+                    ))
+                }
+                json => json,
+            };
+            let value = self.decode(
+                shape(&Bindings::new(), inner),
+                json,
+                &format!("{}.Some", path),
+            )?;
+            return Ok(refs.some(Arc::new(value)));
+        }
+        if is_hash(reference, TUPLE_HASH) {
+            let mut types = vec![];
+            let mut args = args.to_vec();
+            while let [head, tail] = args.as_slice() {
+                types.push(head.clone());
+                args = match shape(&Bindings::new(), tail) {
+                    Shape::Data(r, args) if is_hash(&r, TUPLE_HASH) => args,
+                    Shape::Data(r, _) if is_hash(&r, UNIT_HASH) => vec![],
+                    _ => return Err(format!("{}: Invalid tuple type", path)),
+                };
+            }
+            return match json {
+                Json::Array(items) if items.len() == types.len() => {
+                    let mut values = vec![];
+                    for (i, (typ, json)) in types.iter().zip(items).enumerate() {
+                        values.push(Arc::new(self.decode(
+                            shape(&Bindings::new(), typ),
+                            json,
+                            &format!("{}[{}]", path, i),
+                        )?));
+                    }
+                    Ok(refs.tuple(values))
+                }
+                _ => Err(format!(
+                    "{}: Expected an array of {} items, found {}",
+                    path,
+                    types.len(),
+                    json
+                )),
+            };
+        }
+        let decl = self.data_decl(reference, path)?;
+        let find = |name: &str| {
+            let suffix = format!(".{}", name);
+            decl.constructors
+                .iter()
+                .position(|(sym, _)| sym.text == name || sym.text.ends_with(&suffix))
+                .ok_or_else(|| {
+                    format!("{}: Unknown constructor {} for {:?}", path, name, reference)
+                })
+        };
+        let single = decl.constructors.len() == 1;
+        // (constructor number, fields by position or by name)
+        let (number, fields) = match json {
+            Json::String(name) => (find(name)?, Ok(vec![])),
+            Json::Array(items) if single => (0, Ok(items.iter().collect())),
+            Json::Object(object) if single && self.fields.contains_key(reference) => {
+                (0, Err(object))
+            }
+            Json::Object(object) if object.len() == 1 => {
+                let (name, fields) = object.iter().next().unwrap();
+                match fields {
+                    Json::Array(items) => (find(name)?, Ok(items.iter().collect())),
+                    _ => {
+                        return Err(format!(
+                            "{}.{}: Expected an array of fields, found {}",
+                            path, name, fields
+                        ))
+                    }
+                }
+            }
+            _ => {
+                return Err(format!(
+                    "{}: Expected a constructor of {:?}, found {}",
+                    path, reference, json
+                ))
+            }
+        };
+        let (name, field_types) = self.field_types(decl, args, number, path)?;
+        let fields: Vec<(String, &Json)> = match fields {
+            Ok(items) => {
+                if items.len() != field_types.len() {
+                    return Err(format!(
+                        "{}: {} takes {} fields, found {}",
+                        path,
+                        name.text,
+                        field_types.len(),
+                        items.len()
+                    ));
+                }
+                items
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, json)| (format!("{}.{}.{}", path, short_name(name), i), json))
+                    .collect()
+            }
+            Err(object) => match self.record_fields(reference, decl, field_types.len()) {
+                Some(names) => names
+                    .iter()
+                    .map(|field| match object.get(field) {
+                        Some(json) => Ok((format!("{}.{}", path, field), json)),
+                        None => Err(format!("{}: Missing field {}", path, field)),
+                    })
+                    .collect::<Result<_, String>>()?,
+                None => {
+                    return Err(format!(
+                        "{}: The field names for {} don't match its constructor",
+                        path, name.text
+                    ))
+                }
+            },
+        };
+        if fields.is_empty() {
+            return Ok(Value::Constructor(reference.clone(), number));
+        }
+        let mut values = im::Vector::new();
+        for (typ, (path, json)) in field_types.iter().zip(fields) {
+            values.push_back(Arc::new(self.decode(
+                shape(&Bindings::new(), typ),
+                json,
+                &path,
+            )?));
+        }
+        Ok(Value::PartialConstructor(reference.clone(), number, values))
+    }
+}
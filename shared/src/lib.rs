@@ -1,12 +1,23 @@
+pub mod async_ffi;
+pub mod budget;
+pub mod builtins;
 pub mod check;
 pub mod chrome_trace;
+pub mod concurrent;
 pub mod convert;
+pub mod error;
 pub mod ffi;
 pub mod frame;
+pub mod handlers;
 pub mod ir_exec;
 pub mod ir_runtime;
+pub mod json;
+pub mod memo;
 pub mod pattern;
+pub mod policy;
+pub mod scheduler;
 pub mod stack;
+pub mod stack_trace;
 pub mod state;
 pub mod trace;
 pub mod types;
@@ -25,5 +36,7 @@ pub fn pack(env: &types::RuntimeEnv) -> String {
 
 pub fn unpack(data: &str) -> types::RuntimeEnv {
     let raw = base64::decode(data).expect("Cannot base64 unpack");
-    bincode::deserialize(&raw).expect("Unable to deserialize")
+    let mut env: types::RuntimeEnv = bincode::deserialize(&raw).expect("Unable to deserialize");
+    env.find_either();
+    env
 }
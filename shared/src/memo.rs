@@ -0,0 +1,65 @@
+// The values of top-level terms that don't take arguments or have effects, so
+// that each one is only evaluated once per env, e.g. a big lookup table.
+//
+// The table lives on the RuntimeEnv (but isn't serialized), so it's shared by
+// every run against the env, and a rebuilt or reloaded env starts out empty.
+use super::types::*;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+// `None` for terms that can't be memoised. Terms that can, but haven't been
+// evaluated yet, aren't in here.
+#[derive(Default)]
+pub struct Memo(RwLock<HashMap<Id, Option<Arc<Value>>>>);
+
+impl Memo {
+    pub fn get(&self, id: &Id) -> Option<Arc<Value>> {
+        self.0.read().unwrap().get(id).cloned().flatten()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0
+            .read()
+            .unwrap()
+            .values()
+            .filter(|value| value.is_some())
+            .count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn remove(&self, id: &Id) {
+        self.0.write().unwrap().remove(id);
+    }
+
+    // For when the env's code or builtins change out from under it
+    pub fn clear(&self) {
+        self.0.write().unwrap().clear();
+    }
+}
+
+impl RuntimeEnv {
+    // Whether the term's value should be kept around once it's evaluated.
+    pub fn memoises(&self, id: &Id) -> bool {
+        if let Some(entry) = self.memo.0.read().unwrap().get(id) {
+            return entry.is_some();
+        }
+        let memoisable = match self.terms.get(id) {
+            Some((_, typ)) => {
+                let (args, effects, _) = crate::ir_runtime::extract_args(typ);
+                args.is_empty() && effects.is_empty()
+            }
+            None => false,
+        };
+        if !memoisable {
+            self.memo.0.write().unwrap().insert(id.clone(), None);
+        }
+        memoisable
+    }
+
+    pub fn memoise(&self, id: Id, value: Arc<Value>) {
+        self.memo.0.write().unwrap().insert(id, Some(value));
+    }
+}
@@ -0,0 +1,355 @@
+// A capability policy for abilities that are handled by the host, so that
+// third-party code can be run without handing it everything the FFI can do.
+//
+// Operations are named by the full constructor name, e.g. `native.IO.removeFile`.
+// A rule matches an operation if it's the same name or a namespace containing it,
+// so `native.IO` covers all of the ability's operations. The namespace an ability
+// was added under can be left off, so that rule covers `mylib.native.IO` too, the
+// same as the hosts do when finding their abilities. A rule starting with `#` is a
+// hash prefix, matching every operation of the ability with that hash.
+use super::async_ffi::{AsyncFFI, ValueFuture};
+use super::error::RuntimeError;
+use super::ffi::FFI;
+use super::state::FullRequest;
+use super::types::*;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+// The default policy allows everything.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Policy {
+    // if given, only operations matching one of these rules are allowed
+    pub allow: Option<Vec<String>>,
+    // operations matching any of these are denied, even if they're also allowed
+    pub deny: Vec<String>,
+    // if given, file paths passed to operations must be inside one of these
+    pub directories: Option<Vec<PathBuf>>,
+    // the total number of operations a run is allowed to perform
+    pub max_calls: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Violation {
+    // the operation's name (or the ability reference, if we don't know it)
+    NotAllowed(String),
+    // (operation, path)
+    FileAccess(String, String),
+    // the limit that was reached
+    TooManyCalls(usize),
+}
+
+impl std::fmt::Display for Violation {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Violation::NotAllowed(name) => write!(fmt, "{} isn't allowed", name),
+            Violation::FileAccess(name, path) => write!(
+                fmt,
+                "{} isn't allowed to access {}, which is outside of the allowed directories",
+                name, path
+            ),
+            Violation::TooManyCalls(max) => {
+                write!(fmt, "The limit of {} ability calls was reached", max)
+            }
+        }
+    }
+}
+
+impl Policy {
+    pub fn allows(&self, name: &str, kind: &Reference) -> bool {
+        if self.deny.iter().any(|rule| matches(rule, name, kind)) {
+            return false;
+        }
+        match &self.allow {
+            None => true,
+            Some(allow) => allow.iter().any(|rule| matches(rule, name, kind)),
+        }
+    }
+
+    pub fn allows_path(&self, path: &str) -> bool {
+        match &self.directories {
+            None => true,
+            Some(directories) => {
+                let path = resolve(Path::new(path));
+                directories
+                    .iter()
+                    .any(|directory| path.starts_with(resolve(directory)))
+            }
+        }
+    }
+}
+
+fn matches(rule: &str, name: &str, kind: &Reference) -> bool {
+    if rule.starts_with('#') {
+        return match kind.hash() {
+            Some(hash) => hash.to_string().starts_with(&rule[1..]),
+            None => false,
+        };
+    }
+    let starts = std::iter::once(0).chain(name.match_indices('.').map(|(idx, _)| idx + 1));
+    starts
+        .map(|start| &name[start..])
+        .any(|name| name == rule || (name.starts_with(rule) && name[rule.len()..].starts_with('.')))
+}
+
+// Makes the path absolute & gets rid of `..`s, so you can't get out of a directory
+// with `allowed/../../etc`. The part of the path that exists is canonicalized, so
+// symlinks are followed too.
+fn resolve(path: &Path) -> PathBuf {
+    let absolute = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir().unwrap_or_default().join(path)
+    };
+    let mut normal = PathBuf::new();
+    for component in absolute.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir => {
+                normal.pop();
+            }
+            component => normal.push(component),
+        }
+    }
+    let mut existing = normal.as_path();
+    let mut rest = vec![];
+    loop {
+        if let Ok(mut real) = existing.canonicalize() {
+            for part in rest.iter().rev() {
+                real.push(part);
+            }
+            return real;
+        }
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                rest.push(name.to_owned());
+                existing = parent;
+            }
+            _ => return normal,
+        }
+    }
+}
+
+// Wraps another FFI, checking each request against the policy before
+// it gets handled.
+pub struct PolicyFFI<T: FFI> {
+    pub inner: T,
+    pub policy: Policy,
+    // (ability, constructor number) -> the constructor's name
+    names: HashMap<(Reference, usize), String>,
+    // (ability, constructor number) -> the index of the argument that's a file path
+    path_args: HashMap<(Reference, usize), usize>,
+    pub calls: usize,
+}
+
+impl<T: FFI> PolicyFFI<T> {
+    pub fn new(inner: T, policy: Policy, env: &RuntimeEnv) -> Self {
+        let mut names = HashMap::new();
+        for (id, decl) in env.types.iter() {
+            if let TypeDecl::Effect(data) = decl {
+                for (number, (sym, _)) in data.constructors.iter().enumerate() {
+                    names.insert((Reference::DerivedId(id.clone()), number), sym.text.clone());
+                }
+            }
+        }
+        PolicyFFI {
+            inner,
+            policy,
+            names,
+            path_args: HashMap::new(),
+            calls: 0,
+        }
+    }
+
+    // Marks argument `idx` of the operation `name` as a file path, to be checked
+    // against `policy.directories`. As with the allow & deny rules, `name` can
+    // leave off the namespace that the ability was added under.
+    pub fn path_arg(mut self, name: &str, idx: usize) -> Self {
+        let suffix = format!(".{}", name);
+        for (key, full) in self.names.iter() {
+            if full == name || full.ends_with(&suffix) {
+                self.path_args.insert(key.clone(), idx);
+            }
+        }
+        self
+    }
+
+    fn name(&self, kind: &Reference, number: usize) -> String {
+        match self.names.get(&(kind.clone(), number)) {
+            Some(name) => name.clone(),
+            None => format!("{:?} # {}", kind, number),
+        }
+    }
+
+    fn check(
+        &mut self,
+        kind: &Reference,
+        number: usize,
+        args: &Vec<Arc<Value>>,
+    ) -> Result<(), Violation> {
+        let name = self.name(kind, number);
+        if !self.policy.allows(&name, kind) {
+            return Err(Violation::NotAllowed(name));
+        }
+        if let Some(idx) = self.path_args.get(&(kind.clone(), number)) {
+            match args.get(*idx).map(|arg| &**arg) {
+                Some(Value::Text(path)) if self.policy.allows_path(path) => (),
+                Some(Value::Text(path)) => return Err(Violation::FileAccess(name, path.clone())),
+                _ => return Err(Violation::FileAccess(name, "<not a path>".to_owned())),
+            }
+        }
+        self.calls += 1;
+        match self.policy.max_calls {
+            Some(max) if self.calls > max => Err(Violation::TooManyCalls(max)),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<T: FFI> FFI for PolicyFFI<T> {
+    fn check_request(
+        &mut self,
+        kind: &Reference,
+        number: usize,
+        args: &Vec<Arc<Value>>,
+    ) -> Result<(), RuntimeError> {
+        self.check(kind, number, args)
+            .map_err(RuntimeError::PolicyViolation)?;
+        self.inner.check_request(kind, number, args)
+    }
+
+    fn handle_request_sync(
+        &mut self,
+        typ: &ABT<Type>,
+        kind: &Reference,
+        number: usize,
+        args: &Vec<Arc<Value>>,
+    ) -> Result<Option<Value>, RuntimeError> {
+        self.inner.handle_request_sync(typ, kind, number, args)
+    }
+
+    fn handle_request(&mut self, request: FullRequest) {
+        self.inner.handle_request(request)
+    }
+
+    // An ability that the policy doesn't allow any operations of isn't handled,
+    // so a run that needs it can be turned away before it starts.
+    fn handles(&self, kind: &Reference) -> bool {
+        let mut ops = self
+            .names
+            .iter()
+            .filter(|((reference, _), _)| reference == kind)
+            .peekable();
+        if ops.peek().is_some() && !ops.any(|(_, name)| self.policy.allows(name, kind)) {
+            return false;
+        }
+        self.inner.handles(kind)
+    }
+}
+
+impl<T: AsyncFFI> AsyncFFI for PolicyFFI<T> {
+    fn handle_request_async(
+        &mut self,
+        typ: &ABT<Type>,
+        kind: &Reference,
+        number: usize,
+        args: &Vec<Arc<Value>>,
+    ) -> Option<ValueFuture> {
+        self.inner.handle_request_async(typ, kind, number, args)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct AllFFI;
+
+    impl FFI for AllFFI {
+        fn handle_request_sync(
+            &mut self,
+            _typ: &ABT<Type>,
+            _kind: &Reference,
+            _number: usize,
+            _args: &Vec<Arc<Value>>,
+        ) -> Result<Option<Value>, RuntimeError> {
+            Ok(None)
+        }
+
+        fn handle_request(&mut self, _request: FullRequest) {}
+
+        fn handles(&self, _kind: &Reference) -> bool {
+            true
+        }
+    }
+
+    fn symbol(text: &str) -> Symbol {
+        Symbol {
+            num: 0,
+            text: text.to_owned(),
+            unique: 0,
+        }
+    }
+
+    // `native.IO`, added under `mylib`
+    fn namespaced() -> (RuntimeEnv, Reference) {
+        let mut env = RuntimeEnv::new();
+        let id = Id::from_string("io");
+        let unit = ABT::Tm(Type::Ref(Reference::Builtin("Unit".to_owned())));
+        let constructors = ["getLine", "removeFile"]
+            .iter()
+            .map(|op| (symbol(&format!("mylib.native.IO.{}", op)), unit.clone()))
+            .collect();
+        env.types.insert(
+            id.clone(),
+            TypeDecl::Effect(DataDecl {
+                modifier: Modifier::Structural,
+                bound: vec![],
+                constructors,
+            }),
+        );
+        (env, Reference::DerivedId(id))
+    }
+
+    fn check(policy: Policy, op: usize) -> Result<(), RuntimeError> {
+        let (env, kind) = namespaced();
+        let mut ffi = PolicyFFI::new(AllFFI, policy, &env);
+        ffi.check_request(&kind, op, &vec![Arc::new(Value::Text("file".to_owned()))])
+    }
+
+    #[test]
+    fn deny_rules_apply_to_namespaced_abilities() {
+        let policy = Policy {
+            deny: vec!["native.IO.removeFile".to_owned()],
+            ..Default::default()
+        };
+        assert!(check(policy.clone(), 0).is_ok());
+        match check(policy, 1) {
+            Err(RuntimeError::PolicyViolation(Violation::NotAllowed(name))) => {
+                assert_eq!(name, "mylib.native.IO.removeFile")
+            }
+            other => panic!("Expected removeFile to be denied, got {:?}", other),
+        }
+
+        let policy = Policy {
+            deny: vec!["native.IO".to_owned()],
+            ..Default::default()
+        };
+        let (env, kind) = namespaced();
+        assert!(!PolicyFFI::new(AllFFI, policy, &env).handles(&kind));
+    }
+
+    #[test]
+    fn rules_match_whole_names() {
+        let kind = Reference::Builtin("IO".to_owned());
+        let rule = "native.IO.removeFile";
+        assert!(matches(rule, "native.IO.removeFile", &kind));
+        assert!(matches(rule, "mylib.native.IO.removeFile", &kind));
+        assert!(!matches(rule, "mynative.IO.removeFile", &kind));
+        assert!(!matches(rule, "native.IO.removeFiles", &kind));
+        assert!(matches("native.IO", "a.b.native.IO.getLine", &kind));
+        assert!(!matches("native.IO", "native.IOs.getLine", &kind));
+    }
+}
@@ -0,0 +1,322 @@
+// Running many States at once, by taking turns.
+//
+// Each turn, the task at the front of the queue runs for `slice` instructions
+// (times its priority), and goes to the back. A task that makes a request the FFI
+// can't handle synchronously is parked until the host `resume`s it with the
+// result, and the others keep going in the meantime.
+//
+// Tasks are kept as snapshots between turns, and the env is only needed while
+// stepping, so the scheduler can outlive any one borrow of the env (wasm only
+// locks it for the duration of a run). There's no threads or clocks involved,
+// so it works the same on wasm.
+use super::budget::Budget;
+use super::chrome_trace::Traces;
+use super::error::{Failure, RuntimeError};
+use super::ffi::FFI;
+use super::state::{FullRequest, State, Step, Suspended};
+use super::types::*;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+
+pub type TaskId = usize;
+
+pub enum Event {
+    // The task ran for its turn, and has more to do
+    Yielded(TaskId),
+    Done(TaskId, Arc<Value>),
+    Failed(TaskId, Failure),
+    // The task made a request that the FFI couldn't handle synchronously, and is
+    // parked until it's resumed with the result. See `request`.
+    Parked(TaskId),
+}
+
+enum Next {
+    Continue(Suspended),
+    Parked(FullRequest),
+    // resumed, but the value hasn't been checked against the request yet
+    Resume(FullRequest, Arc<Value>),
+}
+
+struct Task {
+    next: Next,
+    budget: Budget,
+    steps: usize,
+    // A parked request's frames don't know about these, so we carry them over
+    effects: HashMap<String, ABT<Type>>,
+}
+
+pub struct Scheduler {
+    tasks: HashMap<TaskId, Task>,
+    // The ready tasks, in the order they'll get their turns
+    queue: VecDeque<TaskId>,
+    // how many slices a task gets per turn. 0 means it's paused.
+    priorities: HashMap<TaskId, usize>,
+    // Gets every turn for as long as it's ready
+    pinned: Option<TaskId>,
+    next_id: TaskId,
+    // instructions per turn, at priority 1
+    pub slice: usize,
+    // If set, the instructions every task has executed, which their budgets apply
+    // to instead of their own counts. See `share_steps`.
+    shared_steps: Option<usize>,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Scheduler::new()
+    }
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Scheduler {
+            tasks: HashMap::new(),
+            queue: VecDeque::new(),
+            priorities: HashMap::new(),
+            pinned: None,
+            next_id: 0,
+            slice: 10_000,
+            shared_steps: None,
+        }
+    }
+
+    // From now on, the tasks' budgets apply to all of their instructions put together,
+    // e.g. for the threads of one program. The tasks so far count towards it too.
+    pub fn share_steps(&mut self) {
+        let steps = self.tasks.values().map(|task| task.steps).sum();
+        self.shared_steps = Some(steps);
+    }
+
+    // The state's budget still applies, across all of its turns.
+    pub fn spawn(&mut self, state: &State) -> TaskId {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.tasks.insert(
+            id,
+            Task {
+                next: Next::Continue(state.suspend()),
+                budget: state.budget.clone(),
+                steps: state.steps,
+                effects: state.effects.clone(),
+            },
+        );
+        self.priorities.insert(id, 1);
+        self.queue.push_back(id);
+        id
+    }
+
+    // Higher priority tasks run for longer each turn, but everyone gets a turn.
+    pub fn set_priority(&mut self, id: TaskId, priority: usize) {
+        if self.tasks.contains_key(&id) {
+            self.priorities.insert(id, priority.max(1));
+        }
+    }
+
+    // A paused task keeps its place, but is skipped until it's unpaused.
+    pub fn pause(&mut self, id: TaskId) {
+        if self.tasks.contains_key(&id) {
+            self.priorities.insert(id, 0);
+        }
+    }
+
+    pub fn unpause(&mut self, id: TaskId) {
+        if self.priorities.get(&id) == Some(&0) {
+            self.priorities.insert(id, 1);
+        }
+    }
+
+    // Nobody else gets a turn while this task is ready, e.g. for an atomic section.
+    // If it parks, the others run until it's resumed.
+    pub fn pin(&mut self, id: TaskId) {
+        if self.tasks.contains_key(&id) {
+            self.pinned = Some(id);
+        }
+    }
+
+    pub fn unpin(&mut self) {
+        self.pinned = None;
+    }
+
+    pub fn cancel(&mut self, id: TaskId) {
+        self.forget(id);
+        self.tasks.remove(&id);
+    }
+
+    fn forget(&mut self, id: TaskId) {
+        self.priorities.remove(&id);
+        self.queue.retain(|other| *other != id);
+        if self.pinned == Some(id) {
+            self.pinned = None;
+        }
+    }
+
+    // Picks a parked task back up, with `value` as the result of its request.
+    // The value is checked against the request's type when the task next runs.
+    pub fn resume(&mut self, id: TaskId, value: Value) -> Result<(), String> {
+        let mut task = match self.tasks.remove(&id) {
+            Some(task) => task,
+            None => return Err(format!("No task {}", id)),
+        };
+        task.next = match task.next {
+            Next::Parked(request) => Next::Resume(request, Arc::new(value)),
+            next => {
+                task.next = next;
+                self.tasks.insert(id, task);
+                return Err(format!("Task {} isn't waiting on a request", id));
+            }
+        };
+        self.tasks.insert(id, task);
+        self.queue.push_back(id);
+        Ok(())
+    }
+
+    // The request a parked task is waiting on.
+    pub fn request(&self, id: TaskId) -> Option<&FullRequest> {
+        match self.tasks.get(&id) {
+            Some(Task {
+                next: Next::Parked(request),
+                ..
+            }) => Some(request),
+            _ => None,
+        }
+    }
+
+    // Removes a parked task, handing its request over to someone else.
+    pub fn take_request(&mut self, id: TaskId) -> Option<FullRequest> {
+        self.request(id)?;
+        self.forget(id);
+        match self.tasks.remove(&id) {
+            Some(Task {
+                next: Next::Parked(request),
+                ..
+            }) => Some(request),
+            _ => None,
+        }
+    }
+
+    // Removes a parked task, blaming its request for `error`.
+    pub fn fail(&mut self, id: TaskId, error: RuntimeError) -> Option<Failure> {
+        let request = self.take_request(id)?;
+        Some(Failure::new(error, &request.3, request.4))
+    }
+
+    // The effects a task knows how to raise, e.g. for starting another one like it.
+    pub fn effects(&self, id: TaskId) -> Option<&HashMap<String, ABT<Type>>> {
+        self.tasks.get(&id).map(|task| &task.effects)
+    }
+
+    pub fn budget(&self, id: TaskId) -> Option<&Budget> {
+        self.tasks.get(&id).map(|task| &task.budget)
+    }
+
+    pub fn contains(&self, id: TaskId) -> bool {
+        self.tasks.contains_key(&id)
+    }
+
+    pub fn is_parked(&self, id: TaskId) -> bool {
+        self.request(id).is_some()
+    }
+
+    // Whether there's a task that could run right now.
+    pub fn has_ready(&self) -> bool {
+        self.queue.iter().any(|id| self.is_runnable(*id))
+    }
+
+    fn is_runnable(&self, id: TaskId) -> bool {
+        self.priorities.get(&id).map_or(false, |p| *p > 0)
+    }
+
+    // The number of tasks that haven't finished, including parked & paused ones.
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    // The instructions the tasks have executed between them, if they share a budget.
+    pub fn shared_steps(&self) -> Option<usize> {
+        self.shared_steps
+    }
+
+    // Gives the next ready task its turn. `None` if nothing's ready.
+    pub fn step<T: FFI>(
+        &mut self,
+        env: &RuntimeEnv,
+        ffi: &mut T,
+        trace: &mut Traces,
+    ) -> Option<Event> {
+        let pinned = self
+            .pinned
+            .and_then(|pinned| self.queue.iter().position(|id| *id == pinned));
+        let position = match pinned {
+            Some(position) => position,
+            None => self.queue.iter().position(|id| self.is_runnable(*id))?,
+        };
+        let id = self.queue.remove(position)?;
+        let instructions = self.slice.saturating_mul(self.priorities[&id].max(1));
+        let mut task = self.tasks.remove(&id)?;
+        let effects = &task.effects;
+        let state = match task.next {
+            Next::Continue(suspended) => suspended.resume(env),
+            Next::Resume(FullRequest(kind, number, _args, frames, final_index, _t), value) => {
+                State::full_resume(env, kind, number, frames, final_index, value).map(
+                    |mut state| {
+                        state.effects.extend(effects.clone());
+                        state
+                    },
+                )
+            }
+            Next::Parked(_) => unreachable!("Task {} is queued, but it's parked", id),
+        };
+        let mut state = match state {
+            Ok(state) => state,
+            Err(failure) => {
+                self.forget(id);
+                return Some(Event::Failed(id, failure));
+            }
+        };
+        state.budget = task.budget.clone();
+        state.steps = self.shared_steps.unwrap_or(task.steps);
+        let step = state.step(ffi, trace, instructions);
+        task.steps = state.steps;
+        if self.shared_steps.is_some() {
+            self.shared_steps = Some(state.steps);
+        }
+        match step {
+            Ok(Step::Suspended(suspended)) => {
+                task.next = Next::Continue(suspended);
+                self.tasks.insert(id, task);
+                self.queue.push_back(id);
+                Some(Event::Yielded(id))
+            }
+            Ok(Step::Done(value)) => {
+                self.forget(id);
+                Some(Event::Done(id, value))
+            }
+            Ok(Step::Request(request)) => {
+                task.next = Next::Parked(request);
+                self.tasks.insert(id, task);
+                Some(Event::Parked(id))
+            }
+            Err(failure) => {
+                self.forget(id);
+                Some(Event::Failed(id, failure))
+            }
+        }
+    }
+
+    // Takes turns until nothing is ready, i.e. every task is done, parked or paused.
+    // Yields aren't reported.
+    pub fn run<T: FFI>(&mut self, env: &RuntimeEnv, ffi: &mut T, trace: &mut Traces) -> Vec<Event> {
+        let mut events = vec![];
+        while let Some(event) = self.step(env, ffi, trace) {
+            match event {
+                Event::Yielded(_) => (),
+                event => events.push(event),
+            }
+        }
+        events
+    }
+}
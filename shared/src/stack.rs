@@ -1,3 +1,4 @@
+use super::error::RuntimeError;
 use super::frame::{Frame, Source};
 use super::types::*;
 use crate::trace::{Event, Traces};
@@ -35,22 +36,22 @@ impl Stack {
         }
     }
 
-    pub fn get_vbl(&mut self, sym: &Symbol, usage: usize) -> Arc<Value> {
+    pub fn get_vbl(&mut self, sym: &Symbol, usage: usize) -> Result<Arc<Value>, RuntimeError> {
         // if this is the final usage, then pluck it out.
         let idx = match self.frames[0]
             .bindings
             .iter()
             .position(|(a, _, _)| a == sym)
         {
-            None => unreachable!("Variable {:?} not found!", sym),
+            None => return Err(RuntimeError::UnboundVariable(sym.clone())),
             Some(idx) => idx,
         };
         // TODO take usage into account
         if self.frames[0].bindings[idx].1 == usage {
             let (_, _, v) = self.frames[0].bindings.remove(idx);
-            v
+            Ok(v)
         } else {
-            self.frames[0].bindings[idx].2.clone()
+            Ok(self.frames[0].bindings[idx].2.clone())
         }
     }
 
@@ -137,9 +138,9 @@ impl Stack {
         Some((idx, frames, current_idx))
     }
 
-    pub fn pop_frame(&mut self) -> (usize, Arc<Value>) {
+    pub fn pop_frame(&mut self) -> Result<(usize, Arc<Value>), RuntimeError> {
         let idx = self.frames[0].return_index;
-        let value = self.pop().expect("No return value to pop");
+        let value = self.pop_value()?;
         self.traces.finish(self.frames[0].trace_id);
         self.frames.remove(0);
         info!(
@@ -149,9 +150,24 @@ impl Stack {
             value,
             self.frames[0].source
         );
-        (idx, value)
+        Ok((idx, value))
+    }
+    // For tail calls: the new frame takes over the return index of the current one.
+    pub fn replace_frame(&mut self, source: Source) {
+        info!("{} | ----> Replace frame {:?}", self.frames.len(), source);
+        let old_tid = self.frames[0].trace_id;
+        // Only keep a trace entry per call if we're tracing, otherwise
+        // deep tail recursion would grow the traces without bound.
+        let tid = if self.trace {
+            self.traces.finish(old_tid);
+            let tid = self.traces.add(Some((old_tid, false)), source.clone());
+            self.traces.evt(old_tid, Event::NewFrame(tid));
+            tid
+        } else {
+            old_tid
+        };
+        self.frames[0] = Frame::new(source, self.frames[0].return_index, tid);
     }
-    // TODO : fn replace_frame
     pub fn push(&mut self, t: Arc<Value>) {
         // info!("{} | Stack push: {:?}", self.frames.len(), t);
         if self.trace {
@@ -163,13 +179,17 @@ impl Stack {
     pub fn pop(&mut self) -> Option<Arc<Value>> {
         let t = self.frames[0].stack.pop();
         if self.trace {
-            let t_cloned: Value = (&t).as_ref().unwrap().as_ref().clone();
-            self.traces
-                .evt(self.frames[0].trace_id, Event::Pop(t_cloned));
+            if let Some(t) = &t {
+                self.traces
+                    .evt(self.frames[0].trace_id, Event::Pop((**t).clone()));
+            }
         };
         // info!("{} | Stack pop: {:?}", self.frames.len(), t);
         t
     }
+    pub fn pop_value(&mut self) -> Result<Arc<Value>, RuntimeError> {
+        self.pop().ok_or(RuntimeError::EmptyStack)
+    }
     // TODO maybe return a & ref to the Rc?
     pub fn peek(&mut self) -> Option<Arc<Value>> {
         let l = self.frames[0].stack.len();
@@ -181,8 +201,11 @@ impl Stack {
         }
     }
 
-    pub fn pop_to_mark(&mut self) {
-        let mark = self.frames[0].marks.pop().expect("pop to mark");
+    pub fn pop_to_mark(&mut self) -> Result<(), RuntimeError> {
+        let mark = match self.frames[0].marks.pop() {
+            None => return Err(RuntimeError::Internal("No stack mark to pop to".to_owned())),
+            Some(mark) => mark,
+        };
         if self.trace {
             self.traces
                 .evt(self.frames[0].trace_id, Event::PopToMark(mark));
@@ -190,6 +213,7 @@ impl Stack {
         while self.frames[0].stack.len() > mark {
             self.frames[0].stack.pop();
         }
+        Ok(())
     }
     pub fn mark(&mut self) {
         let ln = self.frames[0].stack.len();
@@ -198,11 +222,15 @@ impl Stack {
     pub fn clear_mark(&mut self) {
         self.frames[0].marks.pop();
     }
-    pub fn pop_up(&mut self) {
+    pub fn pop_up(&mut self) -> Result<(), RuntimeError> {
         if self.trace {
             self.traces.evt(self.frames[0].trace_id, Event::PopUp);
         }
         let ln = self.frames[0].stack.len();
+        if ln < 2 {
+            return Err(RuntimeError::EmptyStack);
+        }
         self.frames[0].stack.remove(ln - 2);
+        Ok(())
     }
 }
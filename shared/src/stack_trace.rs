@@ -0,0 +1,70 @@
+use super::frame::{Frame, Source};
+use super::types::*;
+use serde_derive::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TraceFrame {
+    pub source: Source,
+    // For the top frame, this is the instruction being executed.
+    // For the others, it's where execution will continue once the frame above returns.
+    pub idx: usize,
+    pub handler: Option<usize>,
+}
+
+// A snapshot of `Stack::frames`, topmost frame first.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct StackTrace(pub Vec<TraceFrame>);
+
+impl StackTrace {
+    pub fn from_frames(frames: &[Frame], idx: usize) -> Self {
+        StackTrace(
+            frames
+                .iter()
+                .enumerate()
+                .map(|(i, frame)| TraceFrame {
+                    source: frame.source.clone(),
+                    idx: if i == 0 {
+                        idx
+                    } else {
+                        frames[i - 1].return_index
+                    },
+                    handler: frame.handler,
+                })
+                .collect(),
+        )
+    }
+
+    // `name` looks up the codebase name for a term, if there is one.
+    pub fn render<F: Fn(&Id) -> Option<String>>(&self, name: F) -> String {
+        let describe = |id: &Id| {
+            let hash = id.to_string();
+            let short = &hash[0..hash.len().min(10)];
+            match name(id) {
+                Some(name) => format!("{} (#{})", name, short),
+                None => format!("#{}", short),
+            }
+        };
+        self.0
+            .iter()
+            .enumerate()
+            .map(|(i, frame)| {
+                let location = match &frame.source {
+                    Source::Value(id) => describe(id),
+                    Source::Fn(fnid, id) => format!("fn {} in {}", fnid, describe(id)),
+                };
+                let handler = match frame.handler {
+                    Some(handler) => format!(" [handler at {}]", handler),
+                    None => "".to_owned(),
+                };
+                format!("  {}: {} @ {}{}", i, location, frame.idx, handler)
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+impl std::fmt::Display for StackTrace {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        fmt.write_str(&self.render(|_| None))
+    }
+}
@@ -1,20 +1,20 @@
 use super::types::*;
 use super::types::{RuntimeEnv, IR};
 use log::info;
+use serde_derive::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
 
+use crate::budget::Budget;
 use crate::chrome_trace::{Trace, Traces};
+use crate::error::{Failure, RuntimeError};
 use crate::ffi::FFI;
 use crate::frame::{Frame, Source};
 use crate::ir_exec::Ret;
 use crate::stack::Stack;
 
 #[derive(Debug)]
-pub struct InvalidLambda(usize, Value);
-
-#[derive(Debug)]
-pub struct InvalidFFI(Reference, usize, Arc<Value>);
+pub struct InvalidLambda(usize, Box<Value>, Vec<String>);
 
 // impl From<InvalidLambda> for Error {
 //     fn from(other: InvalidLambda) -> Self {
@@ -22,9 +22,9 @@ pub struct InvalidFFI(Reference, usize, Arc<Value>);
 //     }
 // }
 
-impl From<InvalidFFI> for Error {
-    fn from(other: InvalidFFI) -> Self {
-        Error::InvalidFFI(other)
+impl From<Failure> for Error {
+    fn from(other: Failure) -> Self {
+        Error::Failure(other)
     }
 }
 
@@ -38,9 +38,85 @@ pub struct FullRequest(
     pub ABT<Type>,
 );
 
-pub enum Error {
+// The outcome of running a State for a limited number of steps
+#[derive(Debug)]
+pub enum Step {
+    Done(Arc<Value>),
     Request(FullRequest),
-    InvalidFFI(InvalidFFI),
+    Suspended(Suspended),
+}
+
+// Everything needed to pick a State back up later, against the same RuntimeEnv.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Suspended {
+    pub frames: Vec<Frame>,
+    pub idx: usize,
+    pub effects: HashMap<String, ABT<Type>>,
+}
+
+impl Suspended {
+    pub fn to_bytes(&self) -> Result<Vec<u8>, bincode::Error> {
+        bincode::serialize(self)
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, bincode::Error> {
+        bincode::deserialize(data)
+    }
+
+    pub fn resume<'a>(self, env: &'a RuntimeEnv) -> Result<State<'a>, Failure> {
+        let fail = |error| Err(Failure::new(error, &self.frames, self.idx));
+        if self.frames.is_empty() {
+            return fail(RuntimeError::EmptyStack);
+        }
+        // Make sure the env actually has all of the code we're about to jump into,
+        // and that we'd land somewhere inside of it.
+        for (i, frame) in self.frames.iter().enumerate() {
+            let cmds = match &frame.source {
+                Source::Value(hash) => env.terms.get(hash).map(|(cmds, _)| cmds),
+                Source::Fn(fnid, hash) => match env.anon_fns.get(*fnid) {
+                    Some((id, cmds)) if id == hash => Some(cmds),
+                    _ => None,
+                },
+            };
+            let cmds = match cmds {
+                Some(cmds) => cmds,
+                None => {
+                    let hash = match &frame.source {
+                        Source::Value(hash) => hash,
+                        Source::Fn(_, hash) => hash,
+                    };
+                    return fail(RuntimeError::UnknownTerm(hash.clone()));
+                }
+            };
+            // where this frame picks back up
+            let idx = if i == 0 {
+                self.idx
+            } else {
+                self.frames[i - 1].return_index
+            };
+            if idx > cmds.len() || matches!(frame.handler, Some(handler) if handler > cmds.len()) {
+                return fail(RuntimeError::Internal(format!(
+                    "Snapshot jumps outside of {:?}",
+                    frame.source
+                )));
+            }
+        }
+        let stack = Stack::from_frames(self.frames);
+        Ok(State {
+            env,
+            cmds: env.cmds(&stack.frames[0].source),
+            stack,
+            idx: self.idx,
+            effects: self.effects,
+            budget: Budget::unlimited(),
+            steps: 0,
+        })
+    }
+}
+
+pub enum Error {
+    Request(Box<FullRequest>),
+    Failure(Failure),
     // InvalidLambda(InvalidLambda),
 }
 
@@ -51,11 +127,15 @@ pub struct State<'a> {
     pub idx: usize,
     // effects! The concrete versions of any external effects that might be raised
     pub effects: HashMap<String, ABT<Type>>,
+    pub budget: Budget,
+    // the number of instructions executed against the current budget
+    pub steps: usize,
 }
 
+// Keyed by the ability's name. Effects that aren't concrete abilities are ignored.
 pub fn build_effects_map(
     effects: std::collections::HashSet<ABT<Type>>,
-) -> HashMap<String, ABT<Type>> {
+) -> Result<HashMap<String, ABT<Type>>, RuntimeError> {
     use std::iter::FromIterator;
     let mut res = HashMap::new();
     for effect in effects {
@@ -63,12 +143,20 @@ pub fn build_effects_map(
             match effect {
                 ABT::Tm(Type::Effects(contents)) => res.extend(build_effects_map(
                     std::collections::HashSet::from_iter(contents.into_iter()),
-                )),
+                )?),
                 _ => match effect.as_tm().and_then(|tm| tm.ref_name()) {
-                    None => unreachable!("Effect doesn't have a name? {:?}", effect),
+                    None => {
+                        return Err(RuntimeError::Internal(format!(
+                            "Effect doesn't have a name? {:?}",
+                            effect
+                        )))
+                    }
                     Some(name) => {
                         if res.contains_key(&name) {
-                            unreachable!("Multiple effects of different types defined!")
+                            return Err(RuntimeError::Internal(format!(
+                                "Multiple effects of different types defined for {}",
+                                name
+                            )));
                         }
                         res.insert(name, effect);
                     }
@@ -76,7 +164,7 @@ pub fn build_effects_map(
             }
         }
     }
-    res
+    Ok(res)
 }
 
 impl<'a> State<'a> {
@@ -93,6 +181,8 @@ impl<'a> State<'a> {
             idx: 0,
             env: &env,
             effects,
+            budget: Budget::unlimited(),
+            steps: 0,
         }
     }
 
@@ -104,10 +194,10 @@ impl<'a> State<'a> {
         typ: &ABT<Type>,
         effects: HashMap<String, ABT<Type>>,
     ) -> Result<Self, InvalidLambda> {
-        if !crate::check::validate(Default::default(), &typ, &value).is_ok() {
-            return Err(InvalidLambda(fnid, value.clone()));
+        if let Err(errors) = crate::check::validate(env, &typ, &value) {
+            return Err(InvalidLambda(fnid, Box::new(value.clone()), errors));
         }
-        let mut stack = Stack::new(Source::Fn(fnid, Id::from_string("Lambda")), false);
+        let mut stack = Stack::new(Source::Fn(fnid, env.anon_fns[fnid].0.clone()), false);
         stack.frames[0].bindings = bindings;
         stack.push(Arc::new(value));
         Ok(State {
@@ -116,6 +206,8 @@ impl<'a> State<'a> {
             stack,
             idx: 0,
             effects,
+            budget: Budget::unlimited(),
+            steps: 0,
         })
     }
 
@@ -126,13 +218,25 @@ impl<'a> State<'a> {
         frames: Vec<Frame>,
         kidx: usize,
         arg: Arc<Value>,
-    ) -> Result<Self, InvalidFFI> {
-        let constructor_type = env.get_ability_type(&kind, constructor_index);
+    ) -> Result<Self, Failure> {
+        let fail = |error| Failure::new(error, &frames, kidx);
+        let constructor_type = env
+            .get_ability_type(&kind, constructor_index)
+            .map_err(fail)?;
         let (_arg_types, effects, return_type) = crate::ir_runtime::extract_args(&constructor_type);
 
-        if !crate::check::validate(Default::default(), &return_type, &*arg).is_ok() {
-            return Err(InvalidFFI(kind, constructor_index, arg.clone()));
+        if let Err(errors) = crate::check::validate(env, &return_type, &*arg) {
+            return Err(fail(RuntimeError::InvalidFFI(
+                kind,
+                constructor_index,
+                Box::new((*arg).clone()),
+                errors,
+            )));
         }
+        if frames.is_empty() {
+            return Err(fail(RuntimeError::EmptyStack));
+        }
+        let effects = build_effects_map(effects).map_err(fail)?;
         let mut stack = Stack::from_frames(frames);
         stack.push(arg);
         Ok(State {
@@ -140,10 +244,19 @@ impl<'a> State<'a> {
             cmds: env.cmds(&stack.frames[0].source),
             stack,
             idx: kidx,
-            effects: build_effects_map(effects),
+            effects,
+            budget: Budget::unlimited(),
+            steps: 0,
         })
     }
 
+    // Replace the budget & reset the step count. If a previous run ran out of fuel,
+    // calling `run_to_end` again will pick up where it left off.
+    pub fn refuel(&mut self, budget: Budget) {
+        self.budget = budget;
+        self.steps = 0;
+    }
+
     fn resume(&mut self, mut frames: Vec<Frame>, kidx: usize, arg: Arc<Value>) {
         let last = frames.len() - 1;
         frames[last].return_index = self.idx;
@@ -162,46 +275,37 @@ impl<'a> State<'a> {
         self.cmds = self.env.cmds(&self.stack.frames[0].source);
     }
 
-    fn run<T: FFI>(
-        &mut self,
-        ffi: &mut T,
-        trace: &mut Traces,
-        option_ref: &Reference,
-    ) -> Result<(), Error> {
-        #[cfg(not(target_arch = "wasm32"))]
-        let mut n = 0;
+    fn run<T: FFI>(&mut self, ffi: &mut T, trace: &mut Traces) -> Result<(), Error> {
         while self.idx < self.cmds.len() {
-            #[cfg(not(target_arch = "wasm32"))]
-            if n % 100 == 0 {
-                if trace.start.elapsed().as_secs() > 90 {
-                    println!("Ran out of time after {} ticks", n);
-                    return Ok(());
-                    // let message = Arc::new(Value::Text(format!("Ran out of time after {} ticks", n)));
-                    // return message;
-                }
+            // Check before executing anything, so that the state is left intact
+            // and can be resumed with a fresh budget.
+            if self.budget.is_exhausted(self.steps) {
+                return Err(self.fail(RuntimeError::OutOfFuel(self.steps), self.idx));
             }
+            self.steps += 1;
             #[cfg(not(target_arch = "wasm32"))]
             let cstart = std::time::Instant::now();
-            #[cfg(not(target_arch = "wasm32"))]
-            {
-                n += 1;
-            };
             let cidx = self.idx;
 
             let tid = self.stack.frames[0].trace_id;
-            self.stack
-                .traces
-                .evt(tid, crate::trace::Event::IR(cidx, self.cmds[cidx].clone()));
+            if self.stack.trace {
+                self.stack
+                    .traces
+                    .evt(tid, crate::trace::Event::IR(cidx, self.cmds[cidx].clone()));
+            }
 
-            let ret = self.cmds[self.idx].eval(&option_ref, &mut self.stack, &mut self.idx);
+            let ret = self.cmds[self.idx]
+                .eval(&self.env.builtins, &mut self.stack, &mut self.idx)
+                .map_err(|error| self.fail(error, cidx))?;
 
             match &ret {
                 Ret::Nothing => (),
-                ret => {
+                ret if self.stack.trace => {
                     self.stack
                         .traces
                         .evt(tid, crate::trace::Event::Ret(ret.clone()));
                 }
+                _ => (),
             };
 
             #[cfg(not(target_arch = "wasm32"))]
@@ -227,41 +331,132 @@ impl<'a> State<'a> {
                 };
             };
 
-            self.handle_ret(ffi, ret, trace)?;
-            self.handle_tail(trace);
+            self.handle_ret(ffi, ret, trace, cidx)?;
+            self.handle_tail(trace)
+                .map_err(|error| self.fail(error, self.idx))?;
         }
         Ok(())
     }
 
+    // Blame the topmost frame
+    fn fail(&self, error: RuntimeError, idx: usize) -> Error {
+        Error::Failure(Failure::new(error, &self.stack.frames, idx))
+    }
+
     // If it was able to complete synchronously, you get the final value
     // Otherwise, you get None
     pub fn run_to_end<T: FFI>(
         &mut self,
         ffi: &mut T,
         trace: &mut Traces,
-    ) -> Result<Option<Arc<Value>>, InvalidFFI> {
-        let option_ref = Reference::from_hash(crate::convert::OPTION_HASH);
-
-        match self.run(ffi, trace, &option_ref) {
+    ) -> Result<Option<Arc<Value>>, Failure> {
+        match self.run(ffi, trace) {
             Ok(()) => (),
             Err(Error::Request(request)) => {
-                ffi.handle_request(request);
+                ffi.handle_request(*request);
                 return Ok(None);
             }
-            Err(Error::InvalidFFI(error)) => return Err(error),
+            Err(Error::Failure(failure)) => return Err(failure),
             // Err(Error::InvalidLambda(error)) => return Err(error),
         }
 
+        self.final_value().map(Some)
+    }
+
+    // Run at most `n` instructions. If we aren't done by then, you get a snapshot
+    // of the current state, and you can either call `step` again or persist the
+    // snapshot & `Suspended::resume` it later.
+    // Unlike `run_to_end`, a request that can't be handled synchronously is
+    // returned to you instead of being given to `ffi.handle_request`.
+    pub fn step<T: FFI>(
+        &mut self,
+        ffi: &mut T,
+        trace: &mut Traces,
+        n: usize,
+    ) -> Result<Step, Failure> {
+        let start = self.steps;
+        let outer_steps = self.budget.steps;
+        self.budget.steps = Some(match outer_steps {
+            Some(max) => max.min(start + n),
+            None => start + n,
+        });
+        let result = self.run(ffi, trace);
+        self.budget.steps = outer_steps;
+
+        match result {
+            Ok(()) => self.final_value().map(Step::Done),
+            Err(Error::Request(request)) => Ok(Step::Request(*request)),
+            Err(Error::Failure(failure)) if failure.is_resumable() && self.steps >= start + n => {
+                Ok(Step::Suspended(self.suspend()))
+            }
+            Err(Error::Failure(failure)) => Err(failure),
+        }
+    }
+
+    pub fn suspend(&self) -> Suspended {
+        Suspended {
+            frames: self.stack.frames.clone(),
+            idx: self.idx,
+            effects: self.effects.clone(),
+        }
+    }
+
+    fn final_value(&mut self) -> Result<Arc<Value>, Failure> {
         info!("Final stack: {:?}", self.stack);
-        Ok(Some(self.stack.pop().unwrap()))
+        match self.stack.pop_value() {
+            Ok(value) => Ok(value),
+            Err(error) => Err(Failure::new(error, &self.stack.frames, self.idx)),
+        }
+    }
+
+    // Push a frame for a call, or if the call is in tail position, reuse the current one.
+    // Memoised terms always get their own, so we see their value when it returns, and
+    // keep it until then, even if the last thing they do is call something else.
+    fn enter_frame(&mut self, source: Source, trace: &mut Traces) {
+        let memoised = match &source {
+            Source::Value(id) => self.env.memoises(id),
+            Source::Fn(..) => false,
+        } || self.memoised_frame().is_some();
+        if self.is_tail_call() && !memoised {
+            #[cfg(not(target_arch = "wasm32"))]
+            trace.push(&self.stack.frames[0], "E");
+            self.stack.replace_frame(source);
+        } else {
+            self.stack.new_frame(self.idx, source);
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        trace.push(&self.stack.frames[0], "B");
+    }
+
+    // A call is in tail position if nothing that follows it (in the current frame)
+    // can affect the value that the frame returns.
+    // Handler frames are left alone, because `back_to_handler` needs to find them.
+    fn is_tail_call(&self) -> bool {
+        if self.stack.frames[0].handler != None {
+            return false;
+        }
+        let mut idx = self.idx;
+        loop {
+            match self.cmds.get(idx) {
+                None => return true,
+                // only follow forward jumps, so we can't loop forever
+                Some(IR::JumpTo(mark)) if *mark > idx => idx = *mark,
+                Some(IR::Mark(_)) | Some(IR::ClearStackMark) | Some(IR::PopUpOne) => idx += 1,
+                Some(_) => return false,
+            }
+        }
     }
 
-    fn handle_tail(&mut self, trace: &mut Traces) {
+    fn handle_tail(&mut self, trace: &mut Traces) -> Result<(), RuntimeError> {
         while self.idx >= self.cmds.len() {
             #[cfg(not(target_arch = "wasm32"))]
             trace.push(&self.stack.frames[0], "E");
             if self.stack.frames.len() > 1 {
-                let (idx1, value) = self.stack.pop_frame();
+                let memoised = self.memoised_frame();
+                let (idx1, value) = self.stack.pop_frame()?;
+                if let Some(id) = memoised {
+                    self.env.memoise(id, value.clone());
+                }
                 self.idx = idx1;
                 self.stack.push(value);
                 self.cmds = self.env.cmds(&self.stack.frames[0].source);
@@ -270,9 +465,36 @@ impl<'a> State<'a> {
                 break;
             }
         }
+        Ok(())
     }
 
-    fn handle_ret<T>(&mut self, ffi: &mut T, ret: Ret, trace: &mut Traces) -> Result<(), Error>
+    // The term that the top frame is returning the value of, if it's memoised.
+    // A handler's frame is a copy of the one it's set in, so it doesn't count.
+    fn memoised_frame(&self) -> Option<Id> {
+        let frame = &self.stack.frames[0];
+        let id = match &frame.source {
+            Source::Value(id) => id,
+            Source::Fn(..) => return None,
+        };
+        if let Some(below) = self.stack.frames.get(1) {
+            if below.handler != None && below.source == frame.source {
+                return None;
+            }
+        }
+        if self.env.memoises(id) {
+            Some(id.clone())
+        } else {
+            None
+        }
+    }
+
+    fn handle_ret<T>(
+        &mut self,
+        ffi: &mut T,
+        ret: Ret,
+        trace: &mut Traces,
+        cidx: usize,
+    ) -> Result<(), Error>
     where
         T: FFI,
     {
@@ -286,7 +508,12 @@ impl<'a> State<'a> {
                     mark_idx
                 );
                 if self.stack.frames[0].handler != None {
-                    unreachable!("Can't set a handle on a frame that already has one...");
+                    return Err(self.fail(
+                        RuntimeError::Internal(
+                            "Can't set a handle on a frame that already has one".to_owned(),
+                        ),
+                        cidx,
+                    ));
                 }
                 self.stack.frames[0].handler = Some(mark_idx);
                 let ln = self.stack.frames.len();
@@ -323,19 +550,33 @@ impl<'a> State<'a> {
                     .back_again_to_handler(&frames, current_frame_idx)
                 {
                     None => {
-                        let constructor_type = self.env.get_ability_type(&kind, number);
-                        let concrete_type = match self
-                            .effects
-                            .get(&kind.hash().expect("Not a DerivedId").to_string())
+                        let constructor_type = self
+                            .env
+                            .get_ability_type(&kind, number)
+                            .map_err(|error| self.fail(error, cidx))?;
+                        let concrete_type = match kind
+                            .hash()
+                            .and_then(|hash| self.effects.get(&hash.to_string()))
                         {
                             Some(x) => x,
-                            None => unreachable!(
-                                "No effect found: {:?} - {:?}",
-                                kind.hash(),
-                                self.stack.frames[self.stack.frames.len() - 1].source
-                            ),
+                            None => {
+                                return Err(
+                                    self.fail(RuntimeError::MissingEffect(kind.clone()), cidx)
+                                )
+                            }
+                        };
+                        let constructor_args = match concrete_type.as_tm() {
+                            Some(tm) => tm.app_args(),
+                            None => {
+                                return Err(self.fail(
+                                    RuntimeError::Internal(format!(
+                                        "Effect type isn't a Tm: {:?}",
+                                        concrete_type
+                                    )),
+                                    cidx,
+                                ))
+                            }
                         };
-                        let constructor_args = concrete_type.as_tm().unwrap().app_args();
                         // let (arg_types, _effects, return_type) =
                         //     crate::ir_runtime::extract_args(&constructor_type);
                         let (arg_types, _effects, return_type) = crate::ir_runtime::extract_args(
@@ -357,16 +598,21 @@ impl<'a> State<'a> {
                         }
                         // ok folks
 
-                        match ffi.handle_request_sync(&constructor_type, &kind, number, &args) {
+                        ffi.check_request(&kind, number, &args)
+                            .map_err(|error| self.fail(error, cidx))?;
+                        let value = ffi
+                            .handle_request_sync(&constructor_type, &kind, number, &args)
+                            .map_err(|error| self.fail(error, cidx))?;
+                        match value {
                             None => {
-                                return Err(Error::Request(FullRequest(
+                                return Err(Error::Request(Box::new(FullRequest(
                                     kind,
                                     number,
                                     args,
                                     frames,
                                     final_index,
                                     return_type,
-                                )))
+                                ))))
                             }
                             Some(value) => {
                                 // OH TODO ok folks lets just bail here if the type from javascript is wrong
@@ -374,14 +620,18 @@ impl<'a> State<'a> {
                                 // If you're doing an FFI, we have the right to take it to pieces.
                                 // I guess that means I need to be able to return a different kind of
                                 // ship-stopping error
-                                if !crate::check::validate(Default::default(), &return_type, &value)
-                                    .is_ok()
+                                if let Err(errors) =
+                                    crate::check::validate(self.env, &return_type, &value)
                                 {
-                                    return Err(Error::InvalidFFI(InvalidFFI(
-                                        kind,
-                                        number,
-                                        Arc::new(value),
-                                    )));
+                                    return Err(self.fail(
+                                        RuntimeError::InvalidFFI(
+                                            kind,
+                                            number,
+                                            Box::new(value),
+                                            errors,
+                                        ),
+                                        cidx,
+                                    ));
                                 }
                                 self.resume(frames, final_index, Arc::new(value));
                                 return Ok(());
@@ -415,19 +665,33 @@ impl<'a> State<'a> {
                 let final_index = self.idx;
                 let (nidx, saved_frames, frame_idx) = match self.stack.back_to_handler() {
                     None => {
-                        let constructor_type = self.env.get_ability_type(&kind, number);
-                        let concrete_type = match self
-                            .effects
-                            .get(&kind.hash().expect("Not a DerivedId").to_string())
+                        let constructor_type = self
+                            .env
+                            .get_ability_type(&kind, number)
+                            .map_err(|error| self.fail(error, cidx))?;
+                        let concrete_type = match kind
+                            .hash()
+                            .and_then(|hash| self.effects.get(&hash.to_string()))
                         {
                             Some(x) => x,
-                            None => unreachable!(
-                                "No effect found: {:?} - {:?}",
-                                kind.hash(),
-                                self.stack.frames[self.stack.frames.len() - 1].source
-                            ),
+                            None => {
+                                return Err(
+                                    self.fail(RuntimeError::MissingEffect(kind.clone()), cidx)
+                                )
+                            }
+                        };
+                        let constructor_args = match concrete_type.as_tm() {
+                            Some(tm) => tm.app_args(),
+                            None => {
+                                return Err(self.fail(
+                                    RuntimeError::Internal(format!(
+                                        "Effect type isn't a Tm: {:?}",
+                                        concrete_type
+                                    )),
+                                    cidx,
+                                ))
+                            }
                         };
-                        let constructor_args = concrete_type.as_tm().unwrap().app_args();
                         let (arg_types, _effects, return_type) = crate::ir_runtime::extract_args(
                             &constructor_type
                                 .concretize(constructor_args.as_slice(), &Default::default()),
@@ -454,26 +718,35 @@ impl<'a> State<'a> {
                         }
                         // ok folks
 
-                        match ffi.handle_request_sync(&return_type, &kind, number, &args) {
+                        ffi.check_request(&kind, number, &args)
+                            .map_err(|error| self.fail(error, cidx))?;
+                        let value = ffi
+                            .handle_request_sync(&return_type, &kind, number, &args)
+                            .map_err(|error| self.fail(error, cidx))?;
+                        match value {
                             None => {
-                                return Err(Error::Request(FullRequest(
+                                return Err(Error::Request(Box::new(FullRequest(
                                     kind,
                                     number,
                                     args,
                                     self.stack.frames.drain(..).collect(),
                                     final_index,
                                     return_type,
-                                )))
+                                ))))
                             }
                             Some(value) => {
-                                if !crate::check::validate(Default::default(), &return_type, &value)
-                                    .is_ok()
+                                if let Err(errors) =
+                                    crate::check::validate(self.env, &return_type, &value)
                                 {
-                                    return Err(Error::InvalidFFI(InvalidFFI(
-                                        kind,
-                                        number,
-                                        Arc::new(value),
-                                    )));
+                                    return Err(self.fail(
+                                        RuntimeError::InvalidFFI(
+                                            kind,
+                                            number,
+                                            Box::new(value),
+                                            errors,
+                                        ),
+                                        cidx,
+                                    ));
                                 }
 
                                 self.stack.push(Arc::new(value));
@@ -503,27 +776,31 @@ impl<'a> State<'a> {
                 )))
             }
             Ret::FnCall(fnid, bindings, arg) => {
+                let source = Source::Fn(fnid, self.env.anon_fns[fnid].0.clone());
+                self.enter_frame(source, trace);
                 self.cmds = &self.env.anon_fns[fnid].1;
-
-                self.stack.new_frame(
-                    self.idx,
-                    Source::Fn(fnid, self.env.anon_fns[fnid].0.clone()),
-                );
-                #[cfg(not(target_arch = "wasm32"))]
-                trace.push(&self.stack.frames[0], "B");
                 self.stack.frames[0].bindings = bindings;
                 self.stack.frames[0].stack.push(arg);
                 self.idx = 0;
             }
             Ret::Value(hash) => {
-                self.cmds = &self.env.terms.get(&hash).unwrap().0;
-                self.stack.new_frame(self.idx, Source::Value(hash));
-                #[cfg(not(target_arch = "wasm32"))]
-                trace.push(&self.stack.frames[0], "B");
+                if let Some(value) = self.env.memo.get(&hash) {
+                    self.stack.push(value);
+                    return Ok(());
+                }
+                let cmds = match self.env.terms.get(&hash) {
+                    Some((cmds, _)) => cmds,
+                    None => return Err(self.fail(RuntimeError::UnknownTerm(hash), cidx)),
+                };
+                self.enter_frame(Source::Value(hash), trace);
+                self.cmds = cmds;
                 self.idx = 0;
             }
             Ret::HandlePure => {
-                let (idx1, value) = self.stack.pop_frame();
+                let (idx1, value) = self
+                    .stack
+                    .pop_frame()
+                    .map_err(|error| self.fail(error, cidx))?;
                 self.idx = idx1;
                 self.stack.push(value);
                 self.cmds = self.env.cmds(&self.stack.frames[0].source);
@@ -532,3 +809,307 @@ impl<'a> State<'a> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct NoFFI;
+
+    impl FFI for NoFFI {
+        fn handle_request_sync(
+            &mut self,
+            _typ: &ABT<Type>,
+            _kind: &Reference,
+            _number: usize,
+            _args: &Vec<Arc<Value>>,
+        ) -> Result<Option<Value>, RuntimeError> {
+            Ok(None)
+        }
+
+        fn handle_request(&mut self, _request: FullRequest) {}
+
+        fn handles(&self, _kind: &Reference) -> bool {
+            false
+        }
+    }
+
+    fn nat() -> ABT<Type> {
+        ABT::Tm(Type::Ref(Reference::Builtin("Nat".to_owned())))
+    }
+
+    // 42, the long way around: 21 instructions
+    fn counting() -> (RuntimeEnv, Id) {
+        let mut env = RuntimeEnv::new();
+        let id = Id::from_string("counting");
+        let mut cmds = vec![];
+        for n in 0..10 {
+            cmds.push(IR::Value(Value::Nat(n)));
+            cmds.push(IR::Pop);
+        }
+        cmds.push(IR::Value(Value::Nat(42)));
+        env.terms.insert(id.clone(), (cmds, nat()));
+        (env, id)
+    }
+
+    fn builtin(name: &str) -> IR {
+        IR::Value(Value::Ref(Reference::Builtin(name.to_owned())))
+    }
+
+    // `handle loop count with cases {n} -> n`, where
+    // `loop n = if n == 0 then 0 else loop (n - 1)`
+    fn looping(count: u64) -> (RuntimeEnv, Id) {
+        let mut env = RuntimeEnv::new();
+        let id = Id::from_string("looping");
+        let loop_id = Id::from_string("loop");
+        let loop_ref = IR::Value(Value::Ref(Reference::DerivedId(loop_id.clone())));
+        let n = Symbol {
+            num: 0,
+            text: "n".to_owned(),
+            unique: 0,
+        };
+        let loop_body = vec![
+            IR::PopAndName(n.clone(), 2),
+            builtin("Nat.=="),
+            IR::PushSym(n.clone(), 1),
+            IR::Call,
+            IR::Value(Value::Nat(0)),
+            IR::Call,
+            IR::If(9),
+            IR::Value(Value::Nat(0)),
+            IR::JumpTo(16),
+            loop_ref.clone(),
+            builtin("Nat.drop"),
+            IR::PushSym(n, 2),
+            IR::Call,
+            IR::Value(Value::Nat(1)),
+            IR::Call,
+            IR::Call,
+        ];
+        let handler_body = vec![
+            IR::PatternMatch(Pattern::EffectPure(Box::new(Pattern::Var)), false),
+            IR::If(3),
+            IR::PopUpOne,
+        ];
+        env.anon_fns.push((loop_id.clone(), loop_body));
+        env.anon_fns.push((id.clone(), handler_body));
+        env.terms.insert(
+            loop_id,
+            (
+                vec![IR::Fn(0, vec![])],
+                ABT::Tm(Type::Arrow(Box::new(nat()), Box::new(nat()))),
+            ),
+        );
+        let cmds = vec![
+            IR::Handle(5),
+            loop_ref,
+            IR::Value(Value::Nat(count)),
+            IR::Call,
+            IR::HandlePure,
+            IR::Mark(5),
+            IR::Fn(1, vec![]),
+            IR::Swap,
+            IR::Call,
+        ];
+        env.terms.insert(id.clone(), (cmds, nat()));
+        (env, id)
+    }
+
+    // `constant + constant`, where `constant = double 21` has the type `typ`, and
+    // `double n = n + n`
+    fn twice(typ: ABT<Type>) -> (RuntimeEnv, Id, Id) {
+        let mut env = RuntimeEnv::new();
+        let id = Id::from_string("twice");
+        let double_id = Id::from_string("double");
+        let constant_id = Id::from_string("constant");
+        let constant = IR::Value(Value::Ref(Reference::DerivedId(constant_id.clone())));
+        let n = Symbol {
+            num: 0,
+            text: "n".to_owned(),
+            unique: 0,
+        };
+        let double_body = vec![
+            IR::PopAndName(n.clone(), 2),
+            builtin("Nat.+"),
+            IR::PushSym(n.clone(), 1),
+            IR::Call,
+            IR::PushSym(n, 2),
+            IR::Call,
+        ];
+        env.anon_fns.push((double_id.clone(), double_body));
+        env.terms.insert(
+            double_id.clone(),
+            (
+                vec![IR::Fn(0, vec![])],
+                ABT::Tm(Type::Arrow(Box::new(nat()), Box::new(nat()))),
+            ),
+        );
+        let constant_body = vec![
+            IR::Value(Value::Ref(Reference::DerivedId(double_id))),
+            IR::Value(Value::Nat(21)),
+            IR::Call,
+        ];
+        env.terms.insert(constant_id.clone(), (constant_body, typ));
+        let cmds = vec![
+            builtin("Nat.+"),
+            constant.clone(),
+            IR::Call,
+            constant,
+            IR::Call,
+        ];
+        env.terms.insert(id.clone(), (cmds, nat()));
+        (env, id, constant_id)
+    }
+
+    // The value, and how many instructions it took
+    fn run(env: &RuntimeEnv, id: &Id) -> (Arc<Value>, usize) {
+        let mut state = State::new_value(env, id.clone(), false, HashMap::new());
+        match state.step(&mut NoFFI, &mut Traces::new(), 1_000) {
+            Ok(Step::Done(value)) => (value, state.steps),
+            other => panic!("Expected to be done, got {:?}", other),
+        }
+    }
+
+    fn suspended(step: Result<Step, Failure>) -> Suspended {
+        match step {
+            Ok(Step::Suspended(suspended)) => suspended,
+            other => panic!("Expected to be suspended, got {:?}", other),
+        }
+    }
+
+    fn resume_error(suspended: Suspended, env: &RuntimeEnv) -> RuntimeError {
+        match suspended.resume(env) {
+            Ok(_) => panic!("Expected the snapshot to be rejected"),
+            Err(failure) => *failure.error,
+        }
+    }
+
+    #[test]
+    fn step_stops_after_n_instructions() {
+        let (env, id) = counting();
+        let mut state = State::new_value(&env, id, false, HashMap::new());
+        let mut trace = Traces::new();
+        let snapshot = suspended(state.step(&mut NoFFI, &mut trace, 5));
+        assert_eq!(snapshot.idx, 5);
+        assert_eq!(state.steps, 5);
+        match state.step(&mut NoFFI, &mut trace, 100) {
+            Ok(Step::Done(value)) => assert_eq!(*value, Value::Nat(42)),
+            other => panic!("Expected to be done, got {:?}", other),
+        }
+        assert_eq!(state.steps, 21);
+    }
+
+    #[test]
+    fn suspended_states_resume_after_serialization() {
+        let (env, id) = counting();
+        let mut state = State::new_value(&env, id, false, HashMap::new());
+        let mut trace = Traces::new();
+        let bytes = suspended(state.step(&mut NoFFI, &mut trace, 7))
+            .to_bytes()
+            .unwrap();
+        let mut resumed = Suspended::from_bytes(&bytes)
+            .unwrap()
+            .resume(&env)
+            .unwrap_or_else(|failure| panic!("Couldn't resume: {}", failure));
+        let value = resumed.run_to_end(&mut NoFFI, &mut trace).unwrap();
+        assert_eq!(value.as_deref(), Some(&Value::Nat(42)));
+        assert_eq!(resumed.steps, 14);
+    }
+
+    #[test]
+    fn tail_calls_run_in_constant_stack() {
+        let (env, id) = looping(100_000);
+        let mut state = State::new_value(&env, id, false, HashMap::new());
+        let mut trace = Traces::new();
+        let value = loop {
+            match state.step(&mut NoFFI, &mut trace, 1000) {
+                Ok(Step::Suspended(_)) => (),
+                Ok(Step::Done(value)) => break value,
+                other => panic!("Expected to keep going, got {:?}", other),
+            }
+            let depth = state.stack.frames.len();
+            assert!(depth <= 4, "The stack got {} frames deep", depth);
+        };
+        assert_eq!(*value, Value::Nat(0));
+        assert!(state.steps > 1_000_000);
+    }
+
+    #[test]
+    fn tail_calls_keep_handler_frames() {
+        let (env, id) = looping(10);
+        let mut state = State::new_value(&env, id, false, HashMap::new());
+        let mut trace = Traces::new();
+        // Including the handler's own call, which is in tail position
+        let value = loop {
+            match state.step(&mut NoFFI, &mut trace, 1) {
+                Ok(Step::Suspended(_)) => (),
+                Ok(Step::Done(value)) => break value,
+                other => panic!("Expected to keep going, got {:?}", other),
+            }
+            assert!(
+                state.stack.frames.iter().any(|frame| frame.handler != None),
+                "Lost the handler's frame at step {}",
+                state.steps
+            );
+        };
+        assert_eq!(*value, Value::Nat(0));
+    }
+
+    #[test]
+    fn corrupt_snapshots_are_rejected() {
+        let (env, id) = counting();
+        let mut state = State::new_value(&env, id, false, HashMap::new());
+        let mut trace = Traces::new();
+        let snapshot = suspended(state.step(&mut NoFFI, &mut trace, 3));
+
+        assert!(Suspended::from_bytes(&[1, 2, 3]).is_err());
+        let empty = Suspended {
+            frames: vec![],
+            ..snapshot.clone()
+        };
+        match resume_error(empty, &env) {
+            RuntimeError::EmptyStack => (),
+            error => panic!("Wrong error {:?}", error),
+        }
+        let out_of_bounds = Suspended {
+            idx: 100,
+            ..snapshot.clone()
+        };
+        match resume_error(out_of_bounds, &env) {
+            RuntimeError::Internal(_) => (),
+            error => panic!("Wrong error {:?}", error),
+        }
+        match resume_error(snapshot, &RuntimeEnv::new()) {
+            RuntimeError::UnknownTerm(_) => (),
+            error => panic!("Wrong error {:?}", error),
+        }
+    }
+
+    #[test]
+    fn constants_are_only_evaluated_once() {
+        let (env, id, constant) = twice(nat());
+        // at the top level, nothing's memoised
+        let (_, alone) = run(&twice(nat()).0, &constant);
+
+        let (value, first) = run(&env, &id);
+        assert_eq!(*value, Value::Nat(84));
+        assert_eq!(env.memo.get(&constant).as_deref(), Some(&Value::Nat(42)));
+        let (value, second) = run(&env, &id);
+        assert_eq!(*value, Value::Nat(84));
+        // the first run evaluated `constant` once, and the second didn't need to
+        assert_eq!(first, second + alone);
+    }
+
+    #[test]
+    fn effects_and_arguments_arent_memoised() {
+        let effects = ABT::Tm(Type::Effects(vec![ABT::Tm(Type::Ref(Reference::Builtin(
+            "IO".to_owned(),
+        )))]));
+        let (env, id, _) = twice(ABT::Tm(Type::Effect(Box::new(effects), Box::new(nat()))));
+        let (value, first) = run(&env, &id);
+        assert_eq!(*value, Value::Nat(84));
+        assert!(env.memo.is_empty());
+        let (_, second) = run(&env, &id);
+        assert_eq!(first, second);
+    }
+}
@@ -419,6 +419,20 @@ impl Type {
             _ => false,
         }
     }
+
+    // Whether `convert::convert_arg` can build a value of this type: no functions,
+    // effects or type variables.
+    pub fn is_convertible(&self) -> bool {
+        use Type::*;
+        match self {
+            Ann(inner, _) => inner.is_convertible(),
+            App(inner, v) => inner.is_convertible() && v.is_convertible(),
+            Forall(inner) => inner.is_convertible(),
+            IntroOuter(inner) => inner.is_convertible(),
+            Ref(_) => true,
+            _ => false,
+        }
+    }
     // pub fn
 }
 
@@ -435,6 +449,16 @@ impl ABT<Type> {
         }
     }
 
+    pub fn is_convertible(&self) -> bool {
+        use ABT::*;
+        match self {
+            Tm(term) => term.is_convertible(),
+            Cycle(inner) => inner.is_convertible(),
+            Abs(_, _, inner) => inner.is_convertible(),
+            Var(Symbol { text, .. }, _) => text == "()",
+        }
+    }
+
     pub fn ref_name(&self) -> Option<String> {
         use ABT::*;
         match self {
@@ -456,15 +480,16 @@ impl ABT<Type> {
     }
 }
 
-// Runtime values
-#[derive(Debug, Clone, PartialOrd, PartialEq, Serialize, Deserialize)]
+// Runtime values. These are compared structurally; for unison's notion of
+// equality & ordering, see `builtins::universal`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Value {
     Int(i64),
     Nat(u64),
     Float(f64),
     Boolean(bool),
     Text(String),
-    Bytes(Vec<u64>),
+    Bytes(Vector<u8>),
     Char(char),
     Ref(Reference),
     CycleBlank(usize),
@@ -534,7 +559,7 @@ pub enum Term {
     Float(f64),
     Boolean(bool),
     Text(String),
-    Bytes(Vec<u64>),
+    Bytes(Vector<u8>),
     Char(char),
     Blank,
     Ref(Reference),
@@ -801,6 +826,13 @@ pub struct RuntimeEnv {
     pub terms: HashMap<Id, (Vec<IR>, ABT<Type>)>,
     pub types: HashMap<Id, TypeDecl>,
     pub anon_fns: Vec<(Id, Vec<IR>)>, // I think?
+    // Not serialized; you get the default builtins when unpacking,
+    // and hosts can add their own after that.
+    #[serde(skip)]
+    pub builtins: crate::builtins::BuiltinRegistry,
+    // Values of constant terms, filled in as they're evaluated
+    #[serde(skip)]
+    pub memo: crate::memo::Memo,
 }
 
 impl RuntimeEnv {
@@ -809,6 +841,26 @@ impl RuntimeEnv {
             terms: HashMap::new(),
             types: HashMap::new(),
             anon_fns: vec![],
+            builtins: Default::default(),
+            memo: Default::default(),
         }
     }
+
+    // Points the builtins at the codebase's `Either`, for the ones that return it.
+    // Call this once `types` is filled in.
+    pub fn find_either(&mut self) {
+        let is_named = |sym: &Symbol, name: &str| {
+            sym.text == name || sym.text.ends_with(&format!(".{}", name))
+        };
+        self.builtins.refs.either = self.types.iter().find_map(|(id, decl)| match decl {
+            TypeDecl::Data(data)
+                if data.constructors.len() == 2
+                    && is_named(&data.constructors[0].0, "Either.Left")
+                    && is_named(&data.constructors[1].0, "Either.Right") =>
+            {
+                Some(Reference::DerivedId(id.clone()))
+            }
+            _ => None,
+        });
+    }
 }
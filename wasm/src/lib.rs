@@ -3,6 +3,7 @@
 extern crate im;
 extern crate js_sys;
 extern crate log;
+extern crate serde_json;
 extern crate shared;
 extern crate wasm_bindgen;
 extern crate wasm_logger;
@@ -18,6 +19,10 @@ mod unwrap;
 struct Envs {
     count: usize,
     map: HashMap<usize, shared::types::RuntimeEnv>,
+    // max instructions per run, for each env
+    step_budgets: HashMap<usize, usize>,
+    // what the FFI handlers are allowed to be asked to do, for each env
+    policies: HashMap<usize, shared::policy::Policy>,
 }
 
 impl Envs {
@@ -26,10 +31,24 @@ impl Envs {
         self.map.insert(self.count, env);
         self.count
     }
+
+    fn budget(&self, env_id: usize) -> shared::budget::Budget {
+        match self.step_budgets.get(&env_id) {
+            Some(steps) => shared::budget::Budget::steps(*steps),
+            None => shared::budget::Budget::unlimited(),
+        }
+    }
+
+    fn policy(&self, env_id: usize) -> shared::policy::Policy {
+        self.policies.get(&env_id).cloned().unwrap_or_default()
+    }
 }
 
 lazy_static! {
     static ref ENV: Mutex<Envs> = Mutex::new(Default::default());
+    // term hash -> name, for rendering stack traces
+    static ref NAMES: Mutex<HashMap<usize, HashMap<String, String>>> =
+        Mutex::new(Default::default());
 }
 
 // Handlers looks like Vec<(String - hash, usize - fnid for calling back, bool - is it sync)>
@@ -49,8 +68,8 @@ impl shared::ffi::FFI for FFI {
         kind: &Reference,
         number: usize,
         args: &Vec<Arc<Value>>,
-    ) -> Option<Value> {
-        match kind {
+    ) -> Result<Option<Value>, shared::error::RuntimeError> {
+        Ok(match kind {
             Reference::DerivedId(id) => {
                 let js_args = js_sys::Array::new();
                 for arg in args {
@@ -60,23 +79,12 @@ impl shared::ffi::FFI for FFI {
                     let result = f
                         .apply(&JsValue::UNDEFINED, &js_args)
                         .expect("JS Function failed with an error");
-                    if result.is_undefined() || result.is_null() {
-                        shared::unit()
-                    } else {
-                        let (_, _, return_type) = shared::ir_runtime::extract_args(t);
-
-                        match unwrap::wrap(&result, &return_type) {
-                            Some(x) => x,
-                            None => match result.into_serde() {
-                                Err(_) => unreachable!("Not a value {:?}", result),
-                                Ok(r) => r,
-                            },
-                        }
-                    }
+                    let (_, _, return_type) = shared::ir_runtime::extract_args(t);
+                    from_js(&result, &return_type).unwrap_or_else(|err| unreachable!("{}", err))
                 })
             }
             _ => None,
-        }
+        })
     }
 
     fn handles(&self, kind: &Reference) -> bool {
@@ -114,6 +122,51 @@ impl shared::ffi::FFI for FFI {
     }
 }
 
+// For `run_async`, the handlers that aren't sync are called with just the
+// arguments, and can return a Promise.
+impl shared::async_ffi::AsyncFFI for FFI {
+    fn handle_request_async(
+        &mut self,
+        t: &ABT<Type>,
+        kind: &Reference,
+        number: usize,
+        args: &Vec<Arc<Value>>,
+    ) -> Option<shared::async_ffi::ValueFuture> {
+        let id = match kind {
+            Reference::DerivedId(id) => id,
+            _ => return None,
+        };
+        let f = self.0.get(&(id.to_string(), number, false))?;
+        let js_args = js_sys::Array::new();
+        for arg in args {
+            js_args.push(&crate::unwrap::unwrap(&arg));
+        }
+        let result = f.apply(&JsValue::UNDEFINED, &js_args);
+        let (_, _, return_type) = shared::ir_runtime::extract_args(t);
+        Some(Box::pin(async move {
+            let result = result.map_err(|err| format!("JS Function failed with {:?}", err))?;
+            // Plain values are fine too
+            let result = wasm_bindgen_futures::JsFuture::from(js_sys::Promise::resolve(&result))
+                .await
+                .map_err(|err| format!("Promise rejected with {:?}", err))?;
+            from_js(&result, &return_type)
+        }))
+    }
+}
+
+// A handler's result, which should be of type `return_type`.
+fn from_js(result: &JsValue, return_type: &ABT<Type>) -> Result<Value, String> {
+    if result.is_undefined() || result.is_null() {
+        return Ok(shared::unit());
+    }
+    match unwrap::wrap(result, return_type) {
+        Some(x) => Ok(x),
+        None => result
+            .into_serde()
+            .map_err(|_| format!("Not a value {:?}", result)),
+    }
+}
+
 impl From<Vec<JsValue>> for FFI {
     // (ability hash: string, ability index: usize, is_sync: false, handler_function: (...args) => 'a)
     // (ability hash: string, ability index: usize, is_sync: true, handler_function: (...args, kont) => 'a)
@@ -131,6 +184,30 @@ impl From<Vec<JsValue>> for FFI {
     }
 }
 
+// The error message & stack trace, using names from `load_names` if we have them.
+// For `bug` and `todo`, you get a js Error with the unwrapped argument as `error.value`.
+fn runtime_failure(env_id: usize, failure: shared::error::Failure) -> JsValue {
+    let names = NAMES.lock().unwrap();
+    let trace = match names.get(&env_id) {
+        None => failure.trace.to_string(),
+        Some(names) => failure
+            .trace
+            .render(|id| names.get(&id.to_string()).cloned()),
+    };
+    let (kind, value) = match &*failure.error {
+        shared::error::RuntimeError::Bug(value) => ("bug", value),
+        shared::error::RuntimeError::Todo(value) => ("todo", value),
+        error => return JsValue::from(format!("{}\n{}", error, trace)),
+    };
+    let value = unwrap::unwrap(value);
+    let pretty = js_sys::JSON::stringify(&value)
+        .map(String::from)
+        .unwrap_or_else(|_| format!("{:?}", value));
+    let error = js_sys::Error::new(&format!("{}: {}\n{}", kind, pretty, trace));
+    let _ = js_sys::Reflect::set(&error, &JsValue::from("value"), &value);
+    error.into()
+}
+
 #[wasm_bindgen]
 pub fn lambda(
     env_id: usize,
@@ -138,10 +215,13 @@ pub fn lambda(
     arg: JsValue,
     raw_handlers: Vec<JsValue>,
 ) -> Result<JsValue, JsValue> {
-    let mut ffi = FFI::from(raw_handlers);
+    let ffi = FFI::from(raw_handlers);
 
     let mut l = ENV.lock().unwrap();
+    let budget = l.budget(env_id);
+    let policy = l.policy(env_id);
     let env: &mut shared::types::RuntimeEnv = l.map.get_mut(&env_id).unwrap();
+    let mut ffi = shared::policy::PolicyFFI::new(ffi, policy, env);
 
     let value: Value = partial.into_serde().expect("Not a Value");
     let (fnid, bindings, t) = match value {
@@ -178,13 +258,17 @@ pub fn lambda(
         fnid,
         bindings,
         // value,
-        shared::convert::convert_arg(WrappedValue(arg), &arg_type, vec![]).unwrap(),
+        shared::convert::convert_arg(&env, WrappedValue(arg), &arg_type, vec![]).unwrap(),
         &*arg_type,
-        shared::state::build_effects_map(effects_set),
+        shared::state::build_effects_map(effects_set)
+            .map_err(|error| JsValue::from(error.to_string()))?,
     )
     .expect("Invalid Resume arg type");
+    state.budget = budget;
     let mut trace = shared::chrome_trace::Traces::new();
-    let val = state.run_to_end(&mut ffi, &mut trace).unwrap();
+    let val = state
+        .run_to_end(&mut ffi, &mut trace)
+        .map_err(|failure| runtime_failure(env_id, failure))?;
     Ok(val
         .map(|m| crate::unwrap::unwrap(&m))
         .unwrap_or(JsValue::UNDEFINED))
@@ -197,15 +281,20 @@ pub fn resume(
     arg: JsValue,
     raw_handlers: Vec<JsValue>,
 ) -> Result<JsValue, JsValue> {
-    let mut ffi = FFI::from(raw_handlers);
+    let ffi = FFI::from(raw_handlers);
 
     let mut l = ENV.lock().unwrap();
+    let budget = l.budget(env_id);
+    let policy = l.policy(env_id);
     let env: &mut shared::types::RuntimeEnv = l.map.get_mut(&env_id).unwrap();
+    let mut ffi = shared::policy::PolicyFFI::new(ffi, policy, env);
 
     let (kind, constructor_no, frames, kidx): (Reference, usize, Vec<shared::frame::Frame>, usize) =
         kont.into_serde().unwrap();
 
-    let t = env.get_ability_type(&kind, constructor_no);
+    let t = env
+        .get_ability_type(&kind, constructor_no)
+        .map_err(|error| JsValue::from(error.to_string()))?;
 
     let mut state = shared::state::State::full_resume(
         &env,
@@ -213,11 +302,14 @@ pub fn resume(
         constructor_no,
         frames,
         kidx,
-        Arc::new(shared::convert::convert_arg(WrappedValue(arg), &t, vec![]).unwrap()),
+        Arc::new(shared::convert::convert_arg(&env, WrappedValue(arg), &t, vec![]).unwrap()),
     )
-    .expect("Invalid Resume arg type");
+    .map_err(|failure| runtime_failure(env_id, failure))?;
+    state.budget = budget;
     let mut trace = shared::chrome_trace::Traces::new();
-    let val = state.run_to_end(&mut ffi, &mut trace).unwrap();
+    let val = state
+        .run_to_end(&mut ffi, &mut trace)
+        .map_err(|failure| runtime_failure(env_id, failure))?;
     Ok(val
         .map(|m| unwrap::unwrap(&m))
         .unwrap_or(JsValue::UNDEFINED))
@@ -231,10 +323,13 @@ pub fn run_sync(
     raw_handlers: Vec<JsValue>,
 ) -> Result<JsValue, JsValue> {
     // TODO bail if any handlers are async?
-    let mut ffi = FFI::from(raw_handlers);
+    let ffi = FFI::from(raw_handlers);
 
     let mut l = ENV.lock().unwrap();
+    let budget = l.budget(env_id);
+    let policy = l.policy(env_id);
     let env: &mut shared::types::RuntimeEnv = l.map.get_mut(&env_id).unwrap();
+    let mut ffi = shared::policy::PolicyFFI::new(ffi, policy, env);
 
     let hash = shared::types::Id::from_string(term);
     let t = &env.terms.get(&hash).unwrap().1;
@@ -248,8 +343,11 @@ pub fn run_sync(
             )));
         }
     }
-    let args =
-        shared::convert::convert_args(args.into_iter().map(|x| WrappedValue(x)).collect(), &targs)?;
+    let args = shared::convert::convert_args(
+        &env,
+        args.into_iter().map(|x| WrappedValue(x)).collect(),
+        &targs,
+    )?;
 
     let eval_hash = env.add_eval(term, args)?;
 
@@ -257,10 +355,14 @@ pub fn run_sync(
         &env,
         eval_hash,
         false,
-        shared::state::build_effects_map(effects),
+        shared::state::build_effects_map(effects)
+            .map_err(|error| JsValue::from(error.to_string()))?,
     );
+    state.budget = budget;
     let mut trace = shared::chrome_trace::Traces::new();
-    let val = state.run_to_end(&mut ffi, &mut trace).unwrap();
+    let val = state
+        .run_to_end(&mut ffi, &mut trace)
+        .map_err(|failure| runtime_failure(env_id, failure))?;
     Ok(unwrap::unwrap(
         &val.expect("This was expected to be synchronous"),
     ))
@@ -361,10 +463,13 @@ pub fn run(
     args: Vec<JsValue>,
     raw_handlers: Vec<JsValue>,
 ) -> Result<JsValue, JsValue> {
-    let mut ffi = FFI::from(raw_handlers);
+    let ffi = FFI::from(raw_handlers);
 
     let mut l = ENV.lock().unwrap();
+    let budget = l.budget(env_id);
+    let policy = l.policy(env_id);
     let env: &mut shared::types::RuntimeEnv = l.map.get_mut(&env_id).unwrap();
+    let mut ffi = shared::policy::PolicyFFI::new(ffi, policy, env);
 
     let id = shared::types::Id::from_string(term);
     let t = &env.terms.get(&id).unwrap().1;
@@ -387,8 +492,11 @@ pub fn run(
             )));
         }
     }
-    let args =
-        shared::convert::convert_args(args.into_iter().map(|x| WrappedValue(x)).collect(), &targs)?;
+    let args = shared::convert::convert_args(
+        &env,
+        args.into_iter().map(|x| WrappedValue(x)).collect(),
+        &targs,
+    )?;
 
     let eval_hash = env.add_eval(term, args)?;
 
@@ -396,13 +504,155 @@ pub fn run(
         &env,
         eval_hash,
         false,
-        shared::state::build_effects_map(effects),
+        shared::state::build_effects_map(effects)
+            .map_err(|error| JsValue::from(error.to_string()))?,
     );
+    state.budget = budget;
     let mut trace = shared::chrome_trace::Traces::new();
-    let response = state.run_to_end(&mut ffi, &mut trace).unwrap();
+    let response = state
+        .run_to_end(&mut ffi, &mut trace)
+        .map_err(|failure| runtime_failure(env_id, failure))?;
     Ok(JsValue::from_serde(&response).unwrap_or(JsValue::UNDEFINED))
 }
 
+// Like `run`, but requests that aren't handled synchronously are awaited instead of
+// bailing out: their handlers get just the arguments, and can return a Promise.
+// Resolves to the final value.
+#[wasm_bindgen]
+pub fn run_async(
+    env_id: usize,
+    term: &str,
+    args: Vec<JsValue>,
+    raw_handlers: Vec<JsValue>,
+) -> Result<js_sys::Promise, JsValue> {
+    let ffi = FFI::from(raw_handlers);
+
+    let mut l = ENV.lock().unwrap();
+    let budget = l.budget(env_id);
+    let policy = l.policy(env_id);
+    let env: &mut shared::types::RuntimeEnv = l.map.get_mut(&env_id).unwrap();
+    let mut ffi = shared::policy::PolicyFFI::new(ffi, policy, env);
+
+    let id = shared::types::Id::from_string(term);
+    let t = &env.terms.get(&id).unwrap().1;
+    let (targs, effects, _tres) = shared::ir_runtime::extract_args(t);
+    // Threads & friends are handled by the runtime
+    let concurrent = shared::concurrent::ability(&env);
+    for effect in effects.iter() {
+        use shared::ffi::FFI;
+        let kind = effect.as_tm().and_then(|tm| tm.as_reference());
+        if !effect.is_var() && kind != concurrent && !ffi.handles(&kind.unwrap()) {
+            return Err(JsValue::from(format!(
+                "Doesn't handle all effects: {:?}",
+                effect
+            )));
+        }
+    }
+    let args = shared::convert::convert_args(
+        &env,
+        args.into_iter().map(|x| WrappedValue(x)).collect(),
+        &targs,
+    )?;
+
+    let eval_hash = env.add_eval(term, args)?;
+    let effects = shared::state::build_effects_map(effects)
+        .map_err(|error| JsValue::from(error.to_string()))?;
+    drop(l);
+
+    Ok(wasm_bindgen_futures::future_to_promise(async move {
+        use shared::async_ffi::AsyncFFI;
+        use shared::concurrent::{Outcome, Runtime};
+        let mut trace = shared::chrome_trace::Traces::new();
+        let mut runtime = {
+            let l = ENV.lock().unwrap();
+            let env = l.map.get(&env_id).unwrap();
+            let mut state = shared::state::State::new_value(&env, eval_hash, false, effects);
+            state.budget = budget;
+            Runtime::new(&env, &state)
+        };
+        let mut waiting = vec![];
+        loop {
+            // The env is only locked while running, so other runs can
+            // go while we wait on a handler
+            let outcome = {
+                let l = ENV.lock().unwrap();
+                let env = l.map.get(&env_id).unwrap();
+                runtime.run(&env, &mut ffi, &mut trace, shared::concurrent::TURNS)
+            };
+            let (requests, ready) = match outcome {
+                Outcome::Done(value) => return Ok(unwrap::unwrap(&value)),
+                Outcome::Failed(failure) => return Err(runtime_failure(env_id, failure)),
+                Outcome::Killed => return Ok(JsValue::UNDEFINED),
+                Outcome::Pending { requests, ready } => (requests, ready),
+            };
+            for id in requests {
+                let request = match runtime.request(id) {
+                    Some(request) => request,
+                    None => continue,
+                };
+                let future = ffi
+                    .handle_request_async(&request.5, &request.0, request.1, &request.2)
+                    .ok_or_else(|| {
+                        JsValue::from(format!(
+                            "No handler provided for {:?} # {}",
+                            request.0, request.1
+                        ))
+                    })?;
+                waiting.push((id, future));
+            }
+            // Check on the handlers between turns, or wait for one if that's all we can do
+            let mut wait = !ready;
+            while let Some((id, result)) =
+                shared::concurrent::next_completed(&mut waiting, wait).await
+            {
+                wait = false;
+                if let Some(failure) = shared::concurrent::finish(&mut runtime, id, result) {
+                    return Err(runtime_failure(env_id, failure));
+                }
+            }
+        }
+    }))
+}
+
+// Runs against this env will fail with an "out of fuel" error after `steps` instructions.
+#[wasm_bindgen]
+pub fn set_step_budget(env_id: usize, steps: usize) {
+    ENV.lock().unwrap().step_budgets.insert(env_id, steps);
+}
+
+// Requests from runs against this env are checked against the policy (a json
+// object like `{"allow": [...], "deny": [...], "max_calls": n}`), and fail with a
+// "Policy violation" error if it doesn't allow them.
+#[wasm_bindgen]
+pub fn set_policy(env_id: usize, policy: JsValue) -> Result<(), JsValue> {
+    let policy: shared::policy::Policy = policy
+        .into_serde()
+        .map_err(|err| JsValue::from(format!("Invalid policy: {}", err)))?;
+    ENV.lock().unwrap().policies.insert(env_id, policy);
+    Ok(())
+}
+
+// `names` is the `[terms, constructors, types]` json that's written out alongside
+// the packed env, where `terms` maps each hash to a list of names.
+#[wasm_bindgen]
+pub fn load_names(env_id: usize, names: JsValue) -> Result<(), JsValue> {
+    let (terms, _, _): (
+        HashMap<String, Vec<Vec<String>>>,
+        serde_json::Value,
+        serde_json::Value,
+    ) = names
+        .into_serde()
+        .map_err(|err| JsValue::from(format!("Invalid names: {}", err)))?;
+    let mut flat = HashMap::new();
+    for (hash, names) in terms {
+        if let Some(shortest) = names.iter().min_by_key(|name| name.len()) {
+            flat.insert(hash, shortest.join("."));
+        }
+    }
+    NAMES.lock().unwrap().insert(env_id, flat);
+    Ok(())
+}
+
 #[wasm_bindgen]
 pub fn load(data: &str) -> usize {
     console_error_panic_hook::set_once();
@@ -417,11 +667,23 @@ impl shared::convert::ConvertibleArg<WrappedValue> for WrappedValue {
     fn as_f64(&self) -> Option<f64> {
         self.0.as_f64()
     }
+    fn as_bool(&self) -> Option<bool> {
+        self.0.as_bool()
+    }
     fn as_string(&self) -> Option<String> {
         self.0.as_string()
     }
     fn as_list(&self) -> Option<Vec<Self>> {
-        None
+        if js_sys::Array::is_array(&self.0) {
+            Some(
+                js_sys::Array::from(&self.0)
+                    .iter()
+                    .map(|item| WrappedValue(item))
+                    .collect(),
+            )
+        } else {
+            None
+        }
     }
     fn is_empty(&self) -> bool {
         self.0.is_null() || self.0.is_undefined()